@@ -0,0 +1,821 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::constants;
+use crate::opcode::{encode, Opcode};
+
+// Deepest include nesting, guards against files including themselves
+const MAX_INCLUDE_DEPTH: usize = 16;
+// Deepest chain of constants defined by other constants
+const MAX_CONSTANT_DEPTH: usize = 16;
+
+// Names that can't be used for labels, constants or aliases
+const RESERVED: [&str; 10] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "EQU"];
+
+// Assembly error, pointing to the line and column of the source it was found at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+// Assembled ROM, loaded at `PROGRAM_MEMORY_START`
+#[derive(Debug)]
+pub struct Assembly {
+    pub binary: Vec<u8>,
+    // Labels sorted by address
+    pub labels: Vec<(u16, String)>,
+}
+
+impl Assembly {
+    // One `<address> <label>` line per label
+    pub fn symbol_file(&self) -> String {
+        self.labels
+            .iter()
+            .map(|(address, name)| format!("0x{:03X} {}\n", address, name))
+            .collect()
+    }
+}
+
+// Location of a token in the sources
+#[derive(Clone)]
+struct Location {
+    file: Rc<String>,
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.to_string(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    // Mnemonic, register, symbol, number or sprite row
+    Word,
+    // Quoted string, without its quotes
+    Str,
+    Punct(char),
+}
+
+#[derive(Clone)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    location: Location,
+}
+
+impl Token {
+    fn is_word(&self) -> bool {
+        self.kind == TokenKind::Word
+    }
+
+    fn is_punct(&self, punct: char) -> bool {
+        self.kind == TokenKind::Punct(punct)
+    }
+
+    fn upper(&self) -> String {
+        self.text.to_ascii_uppercase()
+    }
+}
+
+enum Symbol {
+    Label(u16),
+    // Expression evaluated when used
+    Constant(Vec<Token>),
+}
+
+// Directive or instruction, encoded once every label is known
+struct Statement {
+    mnemonic: Token,
+    operands: Vec<Vec<Token>>,
+}
+
+// Instruction operand
+enum Operand {
+    Reg(u8),
+    Range(u8, u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Vec<Token>),
+    // Numbers, labels and constants, evaluated from the operand tokens
+    Expr,
+}
+
+// Two pass assembler: the first pass reads the sources and places every statement, the
+// second one encodes them with the addresses of all the labels
+struct Assembler {
+    symbols: HashMap<String, Symbol>,
+    aliases: HashMap<String, u8>,
+    // Labels as spelled in the sources
+    labels: Vec<(u16, String)>,
+    statements: Vec<Statement>,
+    address: usize,
+}
+
+pub fn assemble_file(path: &str) -> Result<Assembly, AsmError> {
+    let mut assembler = Assembler {
+        symbols: HashMap::new(),
+        aliases: HashMap::new(),
+        labels: Vec::new(),
+        statements: Vec::new(),
+        address: constants::PROGRAM_MEMORY_START,
+    };
+    let start = Location {
+        file: Rc::new(path.to_string()),
+        line: 1,
+        column: 1,
+    };
+    assembler.read_file(path, &start, 0)?;
+
+    let mut binary = Vec::new();
+    for statement in &assembler.statements {
+        assembler.encode(statement, &mut binary)?;
+    }
+
+    let mut labels = assembler.labels;
+    labels.sort();
+    Ok(Assembly { binary, labels })
+}
+
+impl Assembler {
+    fn read_file(&mut self, path: &str, from: &Location, depth: usize) -> Result<(), AsmError> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(from.error("includes nested too deeply"));
+        }
+        let source = fs::read_to_string(path)
+            .map_err(|err| from.error(format!("could not read {}: {}", path, err)))?;
+        let file = Rc::new(path.to_string());
+        for (index, text) in source.lines().enumerate() {
+            let tokens = tokenize(text, &file, index + 1)?;
+            self.read_line(tokens, path, depth)?;
+        }
+        Ok(())
+    }
+
+    fn read_line(
+        &mut self,
+        mut tokens: Vec<Token>,
+        path: &str,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        // `name:` labels the current address
+        while tokens.len() >= 2 && tokens[0].is_word() && tokens[1].is_punct(':') {
+            let name = tokens.remove(0);
+            tokens.remove(0);
+            if self.address > u16::MAX as usize {
+                return Err(name.location.error("label outside of memory"));
+            }
+            self.define(&name, Symbol::Label(self.address as u16))?;
+            self.labels.push((self.address as u16, name.text));
+        }
+        if tokens.is_empty() {
+            return Ok(());
+        }
+
+        // `:alias name register`
+        if tokens[0].is_punct(':') {
+            let directive = tokens.get(1).filter(|token| token.upper() == "ALIAS");
+            return match (directive, tokens.get(2), tokens.get(3)) {
+                (Some(_), Some(name), Some(register)) if tokens.len() == 4 => {
+                    let register = self.register(register)?;
+                    self.check_name(name)?;
+                    self.aliases.insert(name.upper(), register);
+                    Ok(())
+                }
+                (Some(directive), _, _) => Err(directive
+                    .location
+                    .error("expected `:alias <name> <register>`")),
+                _ => Err(tokens[0].location.error("expected `:alias`")),
+            };
+        }
+
+        // `name equ value`
+        if tokens.len() >= 2 && tokens[1].is_word() && tokens[1].upper() == "EQU" {
+            if tokens.len() == 2 {
+                return Err(tokens[1].location.error("expected a value after `equ`"));
+            }
+            let name = tokens[0].clone();
+            return self.define(&name, Symbol::Constant(tokens[2..].to_vec()));
+        }
+
+        let mnemonic = tokens.remove(0);
+        if !mnemonic.is_word() {
+            return Err(mnemonic.location.error("expected a mnemonic"));
+        }
+        let operands = split_operands(&mnemonic, tokens)?;
+
+        if mnemonic.upper() == "INCLUDE" {
+            return match operands.as_slice() {
+                [operand] if operand.len() == 1 && operand[0].kind == TokenKind::Str => {
+                    // Relative to the including file
+                    let include = Path::new(path)
+                        .parent()
+                        .unwrap_or_else(|| Path::new(""))
+                        .join(&operand[0].text);
+                    self.read_file(&include.to_string_lossy(), &operand[0].location, depth + 1)
+                }
+                _ => Err(mnemonic.location.error("expected `INCLUDE \"<file>\"`")),
+            };
+        }
+
+        let size = match mnemonic.upper().as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            "SPRITE" => match operands.as_slice() {
+                [row] if row.len() == 1 && matches!(row[0].text.len(), 8 | 16) => {
+                    row[0].text.len() / 8
+                }
+                _ => {
+                    return Err(mnemonic
+                        .location
+                        .error("expected a sprite row of 8 or 16 `#` and `.`"))
+                }
+            },
+            "LD" if operands
+                .get(1)
+                .and_then(|operand| operand.first())
+                .is_some_and(|token| token.upper() == "LONG") =>
+            {
+                4
+            }
+            _ => 2,
+        };
+
+        if self.address + size > constants::XO_CHIP_MEMORY_IN_B {
+            return Err(mnemonic.location.error("program does not fit in memory"));
+        }
+        self.statements.push(Statement { mnemonic, operands });
+        self.address += size;
+        Ok(())
+    }
+
+    fn define(&mut self, name: &Token, symbol: Symbol) -> Result<(), AsmError> {
+        self.check_name(name)?;
+        if self.symbols.contains_key(&name.upper()) {
+            return Err(name
+                .location
+                .error(format!("`{}` is already defined", name.text)));
+        }
+        self.symbols.insert(name.upper(), symbol);
+        Ok(())
+    }
+
+    fn check_name(&self, name: &Token) -> Result<(), AsmError> {
+        let valid = name.is_word()
+            && name
+                .text
+                .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && !name.text.contains('#')
+            && !RESERVED.contains(&name.upper().as_str())
+            && parse_register(&name.text).is_none();
+        if !valid {
+            return Err(name
+                .location
+                .error(format!("`{}` can't be used as a name", name.text)));
+        }
+        Ok(())
+    }
+
+    fn encode(&self, statement: &Statement, binary: &mut Vec<u8>) -> Result<(), AsmError> {
+        let mnemonic = &statement.mnemonic;
+        let operands: Vec<Operand> = statement
+            .operands
+            .iter()
+            .map(|tokens| self.operand(tokens))
+            .collect::<Result<_, _>>()?;
+        let error = |message: &str| mnemonic.location.error(message);
+        let byte = |index: usize| self.value(&statement.operands[index], -128, 0xFF);
+        let nibble = |index: usize| self.value(&statement.operands[index], 0, 0x0F);
+        let addr = |index: usize| {
+            self.value(&statement.operands[index], 0, 0xFFF)
+                .map(|addr| addr as u16)
+        };
+        let bad_operands = || {
+            Err(mnemonic
+                .location
+                .error(format!("invalid operands for `{}`", mnemonic.text)))
+        };
+
+        let opcode = match (mnemonic.upper().as_str(), operands.as_slice()) {
+            ("DB", _) => {
+                for index in 0..operands.len() {
+                    binary.push(byte(index)? as u8);
+                }
+                return Ok(());
+            }
+            ("DW", _) => {
+                for index in 0..operands.len() {
+                    let word = self.value(&statement.operands[index], -0x8000, 0xFFFF)?;
+                    binary.extend_from_slice(&(word as u16).to_be_bytes());
+                }
+                return Ok(());
+            }
+            ("SPRITE", _) => {
+                let row = &statement.operands[0][0];
+                let mut bits: u16 = 0;
+                for (column, pixel) in row.text.chars().enumerate() {
+                    bits = bits << 1
+                        | match pixel {
+                            '#' => 1,
+                            '.' => 0,
+                            _ => {
+                                let mut location = row.location.clone();
+                                location.column += column;
+                                return Err(location.error("sprite rows are made of `#` and `.`"));
+                            }
+                        };
+                }
+                if row.text.len() == 16 {
+                    binary.extend_from_slice(&bits.to_be_bytes());
+                } else {
+                    binary.push(bits as u8);
+                }
+                return Ok(());
+            }
+
+            ("SYS", [Operand::Expr]) => Opcode::Sys(addr(0)?),
+            ("CLS", []) => Opcode::Cls,
+            ("RET", []) => Opcode::Ret,
+            ("SCD", [Operand::Expr]) => Opcode::ScrollDown(nibble(0)? as u8),
+            ("SCU", [Operand::Expr]) => Opcode::ScrollUp(nibble(0)? as u8),
+            ("SCR", []) => Opcode::ScrollRight,
+            ("SCL", []) => Opcode::ScrollLeft,
+            ("EXIT", []) => Opcode::Exit,
+            ("LOW", []) => Opcode::Lores,
+            ("HIGH", []) => Opcode::Hires,
+            ("JP", [Operand::Expr]) => Opcode::Jp(addr(0)?),
+            ("JP", [Operand::Reg(0), Operand::Expr]) => Opcode::JpV0(addr(1)?),
+            ("CALL", [Operand::Expr]) => Opcode::Call(addr(0)?),
+            ("SE", [Operand::Reg(x), Operand::Expr]) => Opcode::SeByte {
+                x: *x,
+                byte: byte(1)? as u8,
+            },
+            ("SE", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::SeReg { x: *x, y: *y },
+            ("SNE", [Operand::Reg(x), Operand::Expr]) => Opcode::SneByte {
+                x: *x,
+                byte: byte(1)? as u8,
+            },
+            ("SNE", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::SneReg { x: *x, y: *y },
+            ("SAVE", [Operand::Range(x, y)]) => Opcode::SaveRange { x: *x, y: *y },
+            ("LOAD", [Operand::Range(x, y)]) => Opcode::LoadRange { x: *x, y: *y },
+            ("LD", [Operand::Reg(x), Operand::Expr]) => Opcode::LdByte {
+                x: *x,
+                byte: byte(1)? as u8,
+            },
+            ("LD", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::LdReg { x: *x, y: *y },
+            ("LD", [Operand::I, Operand::Expr]) => Opcode::LdI(addr(1)?),
+            ("LD", [Operand::I, Operand::Long(tokens)]) => {
+                let address = self.value(tokens, 0, 0xFFFF)? as u16;
+                binary.extend_from_slice(&encode(Opcode::LdILong).to_be_bytes());
+                binary.extend_from_slice(&address.to_be_bytes());
+                return Ok(());
+            }
+            ("LD", [Operand::Reg(x), Operand::Dt]) => Opcode::LdVxDt(*x),
+            ("LD", [Operand::Reg(x), Operand::K]) => Opcode::LdKey(*x),
+            ("LD", [Operand::Dt, Operand::Reg(x)]) => Opcode::LdDt(*x),
+            ("LD", [Operand::St, Operand::Reg(x)]) => Opcode::LdSt(*x),
+            ("LD", [Operand::F, Operand::Reg(x)]) => Opcode::LdF(*x),
+            ("LD", [Operand::Hf, Operand::Reg(x)]) => Opcode::LdHf(*x),
+            ("LD", [Operand::B, Operand::Reg(x)]) => Opcode::Bcd(*x),
+            ("LD", [Operand::IndirectI, Operand::Reg(x)]) => Opcode::Store(*x),
+            ("LD", [Operand::Reg(x), Operand::IndirectI]) => Opcode::Load(*x),
+            ("LD", [Operand::R, Operand::Reg(x)]) => Opcode::SaveFlags(*x),
+            ("LD", [Operand::Reg(x), Operand::R]) => Opcode::LoadFlags(*x),
+            ("ADD", [Operand::Reg(x), Operand::Expr]) => Opcode::AddByte {
+                x: *x,
+                byte: byte(1)? as u8,
+            },
+            ("ADD", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::AddReg { x: *x, y: *y },
+            ("ADD", [Operand::I, Operand::Reg(x)]) => Opcode::AddI(*x),
+            ("OR", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::Or { x: *x, y: *y },
+            ("AND", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::And { x: *x, y: *y },
+            ("XOR", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::Xor { x: *x, y: *y },
+            ("SUB", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::Sub { x: *x, y: *y },
+            ("SUBN", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::Subn { x: *x, y: *y },
+            ("SHR", [Operand::Reg(x)]) => Opcode::Shr { x: *x, y: *x },
+            ("SHR", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::Shr { x: *x, y: *y },
+            ("SHL", [Operand::Reg(x)]) => Opcode::Shl { x: *x, y: *x },
+            ("SHL", [Operand::Reg(x), Operand::Reg(y)]) => Opcode::Shl { x: *x, y: *y },
+            ("RND", [Operand::Reg(x), Operand::Expr]) => Opcode::Rnd {
+                x: *x,
+                byte: byte(1)? as u8,
+            },
+            ("DRW", [Operand::Reg(x), Operand::Reg(y), Operand::Expr]) => Opcode::Drw {
+                x: *x,
+                y: *y,
+                n: nibble(2)? as u8,
+            },
+            ("SKP", [Operand::Reg(x)]) => Opcode::Skp(*x),
+            ("SKNP", [Operand::Reg(x)]) => Opcode::Sknp(*x),
+            ("PLANE", [Operand::Expr]) => {
+                Opcode::Plane(self.value(&statement.operands[0], 0, 3)? as u8)
+            }
+            ("AUDIO", []) => Opcode::Audio,
+            ("PITCH", [Operand::Reg(x)]) => Opcode::Pitch(*x),
+            (
+                "SYS" | "CLS" | "RET" | "SCD" | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH"
+                | "JP" | "CALL" | "SE" | "SNE" | "SAVE" | "LOAD" | "LD" | "ADD" | "OR" | "AND"
+                | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "PLANE"
+                | "AUDIO" | "PITCH",
+                _,
+            ) => return bad_operands(),
+            _ => return Err(error(&format!("unknown mnemonic `{}`", mnemonic.text))),
+        };
+        binary.extend_from_slice(&encode(opcode).to_be_bytes());
+        Ok(())
+    }
+
+    fn operand(&self, tokens: &[Token]) -> Result<Operand, AsmError> {
+        let operand = match tokens {
+            [token] if token.is_word() => match token.upper().as_str() {
+                "I" => Operand::I,
+                "DT" => Operand::Dt,
+                "ST" => Operand::St,
+                "K" => Operand::K,
+                "F" => Operand::F,
+                "HF" => Operand::Hf,
+                "B" => Operand::B,
+                "R" => Operand::R,
+                _ => match self.try_register(token) {
+                    Some(register) => Operand::Reg(register),
+                    None => Operand::Expr,
+                },
+            },
+            [open, i, close] if open.is_punct('[') && i.upper() == "I" && close.is_punct(']') => {
+                Operand::IndirectI
+            }
+            [long, rest @ ..] if long.is_word() && long.upper() == "LONG" => {
+                if rest.is_empty() {
+                    return Err(long.location.error("expected an address after `LONG`"));
+                }
+                Operand::Long(rest.to_vec())
+            }
+            [x, dash, y] if dash.is_punct('-') && self.try_register(x).is_some() => {
+                Operand::Range(self.register(x)?, self.register(y)?)
+            }
+            _ => Operand::Expr,
+        };
+        Ok(operand)
+    }
+
+    fn try_register(&self, token: &Token) -> Option<u8> {
+        if !token.is_word() {
+            return None;
+        }
+        parse_register(&token.text).or_else(|| self.aliases.get(&token.upper()).copied())
+    }
+
+    fn register(&self, token: &Token) -> Result<u8, AsmError> {
+        self.try_register(token).ok_or_else(|| {
+            token
+                .location
+                .error(format!("expected a register, found `{}`", token.text))
+        })
+    }
+
+    // Evaluates an expression and checks it is within [min, max]
+    fn value(&self, tokens: &[Token], min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.eval(tokens, 0)?;
+        if value < min || value > max {
+            return Err(tokens[0].location.error(format!(
+                "value {} out of range, expected {} to {}",
+                value, min, max
+            )));
+        }
+        Ok(value)
+    }
+
+    // Sums of numbers, labels and constants: `[-] term {(+|-) term}`
+    fn eval(&self, tokens: &[Token], depth: usize) -> Result<i64, AsmError> {
+        let mut total: i64 = 0;
+        let mut sign: i64 = 1;
+        let mut expect_term = true;
+        for (index, token) in tokens.iter().enumerate() {
+            match token.kind {
+                TokenKind::Punct(op @ ('+' | '-')) if index == 0 => {
+                    sign = if op == '-' { -1 } else { 1 };
+                }
+                TokenKind::Punct(op @ ('+' | '-')) if !expect_term => {
+                    sign = if op == '-' { -1 } else { 1 };
+                    expect_term = true;
+                }
+                TokenKind::Word if expect_term => {
+                    total = sign
+                        .checked_mul(self.term(token, depth)?)
+                        .and_then(|term| total.checked_add(term))
+                        .ok_or_else(|| token.location.error("value out of range"))?;
+                    sign = 1;
+                    expect_term = false;
+                }
+                _ => {
+                    return Err(token
+                        .location
+                        .error(format!("unexpected `{}` in expression", token.text)))
+                }
+            }
+        }
+        if expect_term {
+            let location = &tokens.last().expect("operands are never empty").location;
+            return Err(location.error("incomplete expression"));
+        }
+        Ok(total)
+    }
+
+    fn term(&self, token: &Token, depth: usize) -> Result<i64, AsmError> {
+        if token.text.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_number(&token.text).ok_or_else(|| {
+                token
+                    .location
+                    .error(format!("invalid number `{}`", token.text))
+            });
+        }
+        match self.symbols.get(&token.upper()) {
+            Some(Symbol::Label(address)) => Ok(*address as i64),
+            Some(Symbol::Constant(tokens)) => {
+                if depth >= MAX_CONSTANT_DEPTH {
+                    return Err(token
+                        .location
+                        .error(format!("`{}` is defined in terms of itself", token.text)));
+                }
+                self.eval(tokens, depth + 1)
+            }
+            None => Err(token
+                .location
+                .error(format!("undefined symbol `{}`", token.text))),
+        }
+    }
+}
+
+// Splits the tokens after the mnemonic at the commas
+fn split_operands(mnemonic: &Token, tokens: Vec<Token>) -> Result<Vec<Vec<Token>>, AsmError> {
+    let mut operands = Vec::new();
+    if tokens.is_empty() {
+        return Ok(operands);
+    }
+    let mut current: Vec<Token> = Vec::new();
+    let mut previous = mnemonic.location.clone();
+    for token in tokens {
+        if token.is_punct(',') {
+            if current.is_empty() {
+                return Err(token.location.error("expected an operand before `,`"));
+            }
+            previous = token.location.clone();
+            operands.push(std::mem::take(&mut current));
+        } else {
+            current.push(token);
+        }
+    }
+    if current.is_empty() {
+        return Err(previous.error("expected an operand after `,`"));
+    }
+    operands.push(current);
+    Ok(operands)
+}
+
+fn tokenize(text: &str, file: &Rc<String>, line: usize) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        let location = Location {
+            file: Rc::clone(file),
+            line,
+            column: pos + 1,
+        };
+        let word_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '#');
+        if c.is_whitespace() {
+            pos += 1;
+        } else if c == ';' {
+            break;
+        } else if c == '"' {
+            let len = chars[pos + 1..]
+                .iter()
+                .position(|&c| c == '"')
+                .ok_or_else(|| location.error("unterminated string"))?;
+            tokens.push(Token {
+                kind: TokenKind::Str,
+                text: chars[pos + 1..pos + 1 + len].iter().collect(),
+                location,
+            });
+            pos += len + 2;
+        } else if word_char(c) {
+            let len = chars[pos..].iter().take_while(|&&c| word_char(c)).count();
+            tokens.push(Token {
+                kind: TokenKind::Word,
+                text: chars[pos..pos + len].iter().collect(),
+                location,
+            });
+            pos += len;
+        } else if matches!(c, ',' | ':' | '[' | ']' | '+' | '-') {
+            tokens.push(Token {
+                kind: TokenKind::Punct(c),
+                text: c.to_string(),
+                location,
+            });
+            pos += 1;
+        } else {
+            return Err(location.error(format!("unexpected character `{}`", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+// `V0` - `VF`
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['V', 'v'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+// Decimal, `0x` hexadecimal or `0b` binary, in either case
+pub fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse::<i64>().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Writes the source files to a fresh directory, the first one being the main file
+    fn write_sources(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chip8_asm_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, source) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, source).unwrap();
+        }
+        dir.join(files[0].0)
+    }
+
+    fn assemble(test: &str, source: &str) -> Result<Assembly, AsmError> {
+        let path = write_sources(test, &[("main.asm", source)]);
+        assemble_file(&path.to_string_lossy())
+    }
+
+    #[test]
+    fn resolves_backward_and_forward_labels() {
+        let assembly = assemble(
+            "labels",
+            "start: LD V1, 0x20\n  JP end ; skip the clear\n  CLS\nend: JP start\n",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.binary,
+            [0x61, 0x20, 0x12, 0x06, 0x00, 0xE0, 0x12, 0x00]
+        );
+        assert_eq!(
+            assembly.labels,
+            [(0x200, "start".to_string()), (0x206, "end".to_string())]
+        );
+        assert_eq!(assembly.symbol_file(), "0x200 start\n0x206 end\n");
+    }
+
+    #[test]
+    fn expands_constants_and_aliases() {
+        let assembly = assemble(
+            "constants",
+            "SPEED equ STEP + 1\nSTEP equ 2\n:alias px V1\nLD px, SPEED\nADD px, -1\n",
+        )
+        .unwrap();
+        assert_eq!(assembly.binary, [0x61, 0x03, 0x71, 0xFF]);
+    }
+
+    #[test]
+    fn reads_data_and_sprite_rows() {
+        let assembly = assemble(
+            "data",
+            "DB 1, 0x02, 0b11\nDW 0x1234\nSPRITE ##..##..\nSPRITE #..............#\n",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.binary,
+            [0x01, 0x02, 0x03, 0x12, 0x34, 0xCC, 0x80, 0x01]
+        );
+    }
+
+    #[test]
+    fn includes_files_relative_to_the_including_one() {
+        let path = write_sources(
+            "include",
+            &[
+                ("main.asm", "LD I, sprite\nINCLUDE \"lib/sprite.asm\"\n"),
+                ("lib/sprite.asm", "sprite: INCLUDE \"row.asm\"\n"),
+                ("lib/row.asm", "SPRITE ########\n"),
+            ],
+        );
+        let assembly = assemble_file(&path.to_string_lossy()).unwrap();
+        assert_eq!(assembly.binary, [0xA2, 0x02, 0xFF]);
+    }
+
+    #[test]
+    fn rejects_files_including_themselves() {
+        let err = assemble("recursive", "INCLUDE \"main.asm\"\n").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 9));
+        assert_eq!(err.message, "includes nested too deeply");
+    }
+
+    #[test]
+    fn errors_point_to_the_line_and_column() {
+        let error = |test: &str, source: &str| {
+            let err = assemble(test, source).unwrap_err();
+            (err.line, err.column, err.message)
+        };
+        assert_eq!(
+            error("range", "CLS\n  LD V0, 300\n"),
+            (
+                2,
+                10,
+                "value 300 out of range, expected -128 to 255".to_string()
+            )
+        );
+        assert_eq!(
+            error("undefined", "JP nowhere\n"),
+            (1, 4, "undefined symbol `nowhere`".to_string())
+        );
+        assert_eq!(
+            error("duplicate", "a: CLS\n a: RET\n"),
+            (2, 2, "`a` is already defined".to_string())
+        );
+        assert_eq!(
+            error("cycle", "ping equ pong\npong equ ping\nJP ping\n"),
+            (2, 10, "`ping` is defined in terms of itself".to_string())
+        );
+        assert_eq!(
+            error("sprite", "SPRITE ##..\n"),
+            (
+                1,
+                1,
+                "expected a sprite row of 8 or 16 `#` and `.`".to_string()
+            )
+        );
+        assert_eq!(
+            error("overflow", "DB 0x7FFFFFFFFFFFFFFF + 1\n"),
+            (1, 25, "value out of range".to_string())
+        );
+        assert_eq!(
+            error(
+                "constant_overflow",
+                "big equ 0x7FFFFFFFFFFFFFFF\nhuge equ big + big\nDB huge\n"
+            ),
+            (2, 16, "value out of range".to_string())
+        );
+        let err = assemble("display", "LD V0, $1\n").unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("main.asm:1:8: unexpected character `$`"));
+    }
+
+    #[test]
+    fn parses_numbers_in_every_base() {
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("-7"), Some(-7));
+        assert_eq!(parse_number("0x1F"), Some(0x1F));
+        assert_eq!(parse_number("0XfF"), Some(0xFF));
+        assert_eq!(parse_number("0b101"), Some(5));
+        assert_eq!(parse_number("0B11"), Some(3));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("12ab"), None);
+    }
+}
@@ -0,0 +1,1426 @@
+use crate::constants;
+use crate::error::Chip8Error;
+use crate::keypad::{KeyEvent, Keypad};
+use crate::opcode::{decode, Opcode, Unknown};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+#[cfg(feature = "std")]
+use crate::state::{StateReader, StateWriter, STATE_MAGIC, STATE_VERSION};
+#[cfg(feature = "std")]
+use crate::trace::{TraceState, Tracer};
+use core::ops::Range;
+#[cfg(feature = "std")]
+use rand::random;
+#[cfg(feature = "std")]
+use std::{cell::RefCell, fs::File, io::Read, rc::Rc};
+
+// Font sprites, 4x5 digits then the SUPER-CHIP 8x10 digits at `BIG_FONT_START`
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+const BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+// CHIP-8 machine, with `MEMORY` bytes of memory and a `SCREEN` pixels display buffer
+// The defaults fit XO-CHIP's 64K and the SUPER-CHIP high resolution, about 74 KB, while
+// `Chip8<{ MEMORY_IN_B }, { LORES_SCREEN_SIZE }>` runs CHIP-8 and CHIP-48 programs in about
+// 6 KB, rejecting quirks that need 64K and the 00FF high resolution instruction
+#[derive(Clone)]
+pub struct Chip8<
+    const MEMORY: usize = { constants::XO_CHIP_MEMORY_IN_B },
+    const SCREEN: usize = { constants::SCREEN_SIZE },
+> {
+    memory: [u8; MEMORY],
+    // Addressable part of the memory
+    memory_size: usize,
+    registers: [u8; constants::NUM_REGISTERS],
+    index_register: u16,
+    program_counter: u16,
+    screen: [u8; SCREEN],
+    // SUPER-CHIP 128x64 high resolution mode
+    hires: bool,
+    // XO-CHIP bitplanes selected for drawing, one bit per plane
+    planes: u8,
+    // XO-CHIP audio pattern buffer, loaded by F002
+    audio_pattern: Option<[u8; constants::AUDIO_PATTERN_SIZE]>,
+    // XO-CHIP audio pitch register
+    pitch: u8,
+    delay_timer: u8,
+    sound_timer: u8,
+    stack: [u16; constants::MAX_STACK_LEVELS],
+    stack_pointer: usize,
+    // Number of usable stack levels
+    stack_depth: usize,
+    // SUPER-CHIP RPL user flags
+    rpl_flags: [u8; constants::NUM_RPL_FLAGS],
+    // Program stopped by 00FD
+    exited: bool,
+    // Random number generator for CXNN
+    rng: Rng,
+
+    // Interpreter specific behaviours
+    quirks: Quirks,
+    // Instructions executed per 60 Hz frame
+    cycles_per_frame: u32,
+    // Flag for clearing screen
+    clear_screen_flag: bool,
+    // Flag for updating screen
+    update_screen_flag: bool,
+    // Flag for beep sound
+    beep_sound_flag: bool,
+    // Display refreshed since the last draw, for the display wait quirk
+    vblank: bool,
+    // Pending FX0A key wait
+    key_wait: Option<KeyWait>,
+    // Memory accessed by the last instruction, only recorded for debugger watchpoints
+    memory_log: MemoryLog,
+    // Execution trace output, shared by the snapshots of the machine
+    #[cfg(feature = "std")]
+    tracer: Option<Rc<RefCell<Tracer>>>,
+    // Debug flag
+    #[cfg(feature = "std")]
+    debug: bool,
+}
+
+// Memory read or written by an instruction, instruction fetches are not included
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: usize,
+    pub write: bool,
+}
+
+// Fixed size log of the memory accesses of one instruction, so recording never allocates
+#[derive(Clone, Copy)]
+struct MemoryLog {
+    enabled: bool,
+    accesses: [MemoryAccess; constants::MAX_MEMORY_ACCESSES],
+    len: usize,
+}
+
+impl MemoryLog {
+    fn push(&mut self, access: MemoryAccess) {
+        if self.enabled && self.len < self.accesses.len() {
+            self.accesses[self.len] = access;
+            self.len += 1;
+        }
+    }
+}
+
+// FX0A state, waiting for a key to be pressed and released
+#[derive(Clone, Copy)]
+struct KeyWait {
+    register: usize,
+    pressed: Option<u8>,
+}
+
+#[cfg(feature = "std")]
+impl Chip8 {
+    // Loads a ROM file, CXNN starts from a random seed
+    pub fn new(
+        rom_file_path: &str,
+        cycles_per_frame: u32,
+        stack_depth: usize,
+        quirks: Quirks,
+        debug: bool,
+    ) -> Result<Self, Chip8Error> {
+        let rom = Self::read_rom(rom_file_path)?;
+        let mut chip =
+            Self::from_rom(&rom, cycles_per_frame, stack_depth, quirks, random::<u64>())?;
+        chip.debug = debug;
+        if debug {
+            println!("{}", DebugData::new(&chip, None));
+        }
+        Ok(chip)
+    }
+
+    // Loads a ROM supplied by the host, CXNN starts from the given seed
+    // The machine is returned by value, hosts with a small stack or another machine size
+    // boot an `empty` one in place with `load_rom` instead
+    pub fn from_rom(
+        rom: &[u8],
+        cycles_per_frame: u32,
+        stack_depth: usize,
+        quirks: Quirks,
+        seed: u64,
+    ) -> Result<Self, Chip8Error> {
+        let mut chip = Self::empty();
+        chip.load_rom(rom, cycles_per_frame, stack_depth, quirks, seed)?;
+        Ok(chip)
+    }
+
+    fn read_rom(rom_file_path: &str) -> Result<Vec<u8>, Chip8Error> {
+        let mut rom = Vec::new();
+        File::open(rom_file_path)
+            .and_then(|mut file| file.read_to_end(&mut rom))
+            .map_err(|_| Chip8Error::RomNotFound(rom_file_path.to_string()))?;
+        Ok(rom)
+    }
+}
+
+impl<const MEMORY: usize, const SCREEN: usize> Chip8<MEMORY, SCREEN> {
+    // Machine without a program, which can be built at compile time into a static and
+    // booted there with `load_rom`
+    pub const fn empty() -> Self {
+        const {
+            assert!(MEMORY >= constants::MEMORY_IN_B && MEMORY <= constants::XO_CHIP_MEMORY_IN_B);
+            assert!(SCREEN >= constants::LORES_SCREEN_SIZE && SCREEN <= constants::SCREEN_SIZE);
+        }
+        Chip8 {
+            memory: [0; MEMORY],
+            memory_size: constants::MEMORY_IN_B,
+            registers: [0; constants::NUM_REGISTERS],
+            index_register: 0,
+            program_counter: constants::PROGRAM_MEMORY_START as u16,
+            screen: [0; SCREEN],
+            hires: false,
+            planes: 0x01,
+            audio_pattern: None,
+            pitch: constants::DEFAULT_PITCH,
+            delay_timer: 0,
+            sound_timer: 0,
+            stack: [0; constants::MAX_STACK_LEVELS],
+            stack_pointer: 0,
+            stack_depth: constants::STACK_LEVELS,
+            rpl_flags: [0; constants::NUM_RPL_FLAGS],
+            exited: false,
+            rng: Rng::new(0),
+            quirks: Quirks::cowgod(),
+            cycles_per_frame: 1,
+            clear_screen_flag: false,
+            update_screen_flag: false,
+            beep_sound_flag: false,
+            vblank: true,
+            key_wait: None,
+            memory_log: MemoryLog {
+                enabled: false,
+                accesses: [MemoryAccess {
+                    address: 0,
+                    write: false,
+                }; constants::MAX_MEMORY_ACCESSES],
+                len: 0,
+            },
+            #[cfg(feature = "std")]
+            tracer: None,
+            #[cfg(feature = "std")]
+            debug: false,
+        }
+    }
+
+    // Resets the machine in place and loads a ROM, CXNN starts from the given seed
+    // The tracer, debug output and memory log setting are kept, and nothing changes when
+    // the ROM or the quirks don't fit the machine
+    pub fn load_rom(
+        &mut self,
+        rom: &[u8],
+        cycles_per_frame: u32,
+        stack_depth: usize,
+        quirks: Quirks,
+        seed: u64,
+    ) -> Result<(), Chip8Error> {
+        let memory_size = quirks.memory_size();
+        if memory_size > MEMORY {
+            return Err(Chip8Error::MemoryTooSmall {
+                size: memory_size,
+                max_size: MEMORY,
+            });
+        }
+        let max_rom_size = memory_size - constants::PROGRAM_MEMORY_START;
+        if rom.len() > max_rom_size {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max_size: max_rom_size,
+            });
+        }
+
+        // RAM Memory
+        self.memory.fill(0);
+        self.memory[..FONT.len()].copy_from_slice(&FONT);
+        self.memory[constants::BIG_FONT_START..constants::BIG_FONT_START + BIG_FONT.len()]
+            .copy_from_slice(&BIG_FONT);
+        self.memory[constants::PROGRAM_MEMORY_START..constants::PROGRAM_MEMORY_START + rom.len()]
+            .copy_from_slice(rom);
+        self.memory_size = memory_size;
+
+        self.registers = [0; constants::NUM_REGISTERS];
+        self.index_register = 0;
+        self.program_counter = constants::PROGRAM_MEMORY_START as u16;
+        self.screen.fill(0);
+        self.hires = false;
+        self.planes = 0x01;
+        self.audio_pattern = None;
+        self.pitch = constants::DEFAULT_PITCH;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.stack = [0; constants::MAX_STACK_LEVELS];
+        self.stack_pointer = 0;
+        self.stack_depth = stack_depth.clamp(1, constants::MAX_STACK_LEVELS);
+        self.rpl_flags = [0; constants::NUM_RPL_FLAGS];
+        self.exited = false;
+        self.rng = Rng::new(seed);
+        self.quirks = quirks;
+        self.cycles_per_frame = cycles_per_frame.max(1);
+        self.clear_screen_flag = false;
+        self.update_screen_flag = false;
+        self.beep_sound_flag = false;
+        self.vblank = true;
+        self.key_wait = None;
+        self.memory_log.len = 0;
+        Ok(())
+    }
+
+    // Pixels of the current resolution, row by row
+    pub fn get_screen(&self) -> &[u8] {
+        &self.screen[..self.get_screen_width() * self.get_screen_height()]
+    }
+
+    pub fn get_screen_width(&self) -> usize {
+        if self.hires {
+            constants::HIRES_SCREEN_WIDTH
+        } else {
+            constants::SCREEN_WIDTH
+        }
+    }
+
+    pub fn get_screen_height(&self) -> usize {
+        if self.hires {
+            constants::HIRES_SCREEN_HEIGHT
+        } else {
+            constants::SCREEN_HEIGHT
+        }
+    }
+
+    pub fn get_registers(&self) -> &[u8; constants::NUM_REGISTERS] {
+        &self.registers
+    }
+
+    pub fn get_index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn get_program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    // Return addresses currently on the stack, innermost last
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer]
+    }
+
+    pub fn get_stack_depth(&self) -> usize {
+        self.stack_depth
+    }
+
+    pub fn get_memory(&self) -> &[u8] {
+        &self.memory[..self.memory_size]
+    }
+
+    // Whether FX0A is waiting for a key to be pressed and released
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    // Starts or stops recording the memory accesses of every instruction
+    pub fn set_memory_log(&mut self, enabled: bool) {
+        self.memory_log.enabled = enabled;
+        self.memory_log.len = 0;
+    }
+
+    // Memory accessed by the last instruction, empty unless recording is enabled
+    pub fn get_memory_accesses(&self) -> &[MemoryAccess] {
+        &self.memory_log.accesses[..self.memory_log.len]
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // Setters for debuggers, callers keep register indexes and addresses in bounds
+    pub fn set_register(&mut self, register: usize, value: u8) {
+        self.registers[register] = value;
+    }
+
+    pub fn set_index_register(&mut self, value: u16) {
+        self.index_register = value;
+    }
+
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.program_counter = value;
+    }
+
+    // Levels above the previous stack pointer keep their last return address
+    pub fn set_stack_pointer(&mut self, value: usize) {
+        self.stack_pointer = value;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+        self.beep_sound_flag = value > 0;
+    }
+
+    pub fn set_memory(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+    }
+
+    // Audio pattern loaded by F002, if any, played back at `get_pattern_rate`
+    pub fn get_audio_pattern(&self) -> Option<&[u8; constants::AUDIO_PATTERN_SIZE]> {
+        self.audio_pattern.as_ref()
+    }
+
+    // XO-CHIP pitch register, 64 plays the audio pattern at 4000 bits/s
+    pub fn get_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // Playback rate of the audio pattern [bits/s]
+    #[cfg(feature = "std")]
+    pub fn get_pattern_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    pub fn should_beep(&self) -> bool {
+        self.beep_sound_flag
+    }
+
+    pub fn should_clear_screen(&self) -> bool {
+        self.clear_screen_flag
+    }
+
+    pub fn should_update_screen(&self) -> bool {
+        self.update_screen_flag
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    // Writes a line for every executed instruction
+    #[cfg(feature = "std")]
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(Rc::new(RefCell::new(tracer)));
+    }
+
+    // Replaces the random number generator of CXNN
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    // Restarts the random number generator of CXNN from the given seed
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
+
+    // Current random number generator state, reseeding with it replays the same numbers
+    pub fn get_seed(&self) -> u64 {
+        self.rng.get_state()
+    }
+
+    pub fn uses_vip_rng(&self) -> bool {
+        self.rng.is_vip()
+    }
+
+    // Snapshot of the whole machine
+    #[cfg(feature = "std")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(STATE_MAGIC, STATE_VERSION);
+        writer.write_u8(self.quirks.to_bits());
+        writer.write_u32(self.memory_size as u32);
+        writer.write_bytes(&self.memory[..self.memory_size]);
+        writer.write_bytes(&self.registers);
+        writer.write_u16(self.index_register);
+        writer.write_u16(self.program_counter);
+        // Always the full SUPER-CHIP screen, states move between machines of any size
+        writer.write_bytes(&self.screen);
+        writer.write_bytes(&[0; constants::SCREEN_SIZE][SCREEN..]);
+        writer.write_bool(self.hires);
+        writer.write_u8(self.planes);
+        writer.write_bool(self.audio_pattern.is_some());
+        writer.write_bytes(&self.audio_pattern.unwrap_or_default());
+        writer.write_u8(self.pitch);
+        writer.write_u8(self.delay_timer);
+        writer.write_u8(self.sound_timer);
+        writer.write_u8(self.stack_depth as u8);
+        writer.write_u8(self.stack_pointer as u8);
+        self.get_stack()
+            .iter()
+            .for_each(|&address| writer.write_u16(address));
+        writer.write_bytes(&self.rpl_flags);
+        writer.write_bool(self.exited);
+        writer.write_u64(self.rng.get_state());
+        writer.write_u32(self.cycles_per_frame);
+        writer.write_bool(self.beep_sound_flag);
+        writer.write_bool(self.vblank);
+        writer.write_bool(self.key_wait.is_some());
+        let key_wait = self.key_wait.unwrap_or(KeyWait {
+            register: 0,
+            pressed: None,
+        });
+        writer.write_u8(key_wait.register as u8);
+        writer.write_bool(key_wait.pressed.is_some());
+        writer.write_u8(key_wait.pressed.unwrap_or_default());
+        writer.finish()
+    }
+
+    // Restores a snapshot taken by `save_state`, leaving the machine untouched on error
+    // Everything is read and checked before the machine changes, states from a bigger
+    // machine load as long as what they use fits this one
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        let invalid = |reason: &str| Chip8Error::InvalidState(reason.to_string());
+        let mut reader =
+            StateReader::new(state, STATE_MAGIC, STATE_VERSION, Chip8Error::InvalidState)?;
+
+        let quirks = Quirks::from_bits(reader.read_u8()?);
+        let memory_size = reader.read_u32()? as usize;
+        if memory_size != quirks.memory_size() {
+            return Err(invalid("memory size does not match the quirks"));
+        }
+        if memory_size > MEMORY {
+            return Err(invalid("memory does not fit the machine"));
+        }
+        let memory = reader.read_bytes(memory_size)?;
+        let registers = reader.read_bytes(constants::NUM_REGISTERS)?;
+        let index_register = reader.read_u16()?;
+        let program_counter = reader.read_u16()?;
+        let screen = reader.read_bytes(constants::SCREEN_SIZE)?;
+        let hires = reader.read_bool()?;
+        if hires && SCREEN < constants::SCREEN_SIZE
+            || screen[SCREEN..].iter().any(|&pixel| pixel != 0)
+        {
+            return Err(invalid("screen does not fit the machine"));
+        }
+        let planes = reader.read_u8()? & 0x03;
+        let has_audio_pattern = reader.read_bool()?;
+        let audio_pattern = reader.read_bytes(constants::AUDIO_PATTERN_SIZE)?;
+        let pitch = reader.read_u8()?;
+        let delay_timer = reader.read_u8()?;
+        let sound_timer = reader.read_u8()?;
+        let stack_depth = reader.read_u8()? as usize;
+        let stack_pointer = reader.read_u8()? as usize;
+        if stack_depth == 0
+            || stack_depth > constants::MAX_STACK_LEVELS
+            || stack_pointer > stack_depth
+        {
+            return Err(invalid("stack pointer out of bounds"));
+        }
+        let mut stack = [0; constants::MAX_STACK_LEVELS];
+        for address in &mut stack[..stack_pointer] {
+            *address = reader.read_u16()?;
+        }
+        let rpl_flags = reader.read_bytes(constants::NUM_RPL_FLAGS)?;
+        let exited = reader.read_bool()?;
+        let rng_state = reader.read_u64()?;
+        let cycles_per_frame = reader.read_u32()?.max(1);
+        let beep_sound_flag = reader.read_bool()?;
+        let vblank = reader.read_bool()?;
+        let waiting_for_key = reader.read_bool()?;
+        let register = reader.read_u8()? as usize;
+        let has_pressed = reader.read_bool()?;
+        let pressed = reader.read_u8()?;
+        if register >= constants::NUM_REGISTERS {
+            return Err(invalid("key wait register out of bounds"));
+        }
+        reader.finish()?;
+
+        self.quirks = quirks;
+        self.memory_size = memory_size;
+        self.memory[..memory_size].copy_from_slice(memory);
+        self.memory[memory_size..].fill(0);
+        self.registers.copy_from_slice(registers);
+        self.index_register = index_register;
+        self.program_counter = program_counter;
+        self.screen.copy_from_slice(&screen[..SCREEN]);
+        self.hires = hires;
+        self.planes = planes;
+        let mut pattern = [0; constants::AUDIO_PATTERN_SIZE];
+        pattern.copy_from_slice(audio_pattern);
+        self.audio_pattern = has_audio_pattern.then_some(pattern);
+        self.pitch = pitch;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.stack_depth = stack_depth;
+        self.stack_pointer = stack_pointer;
+        self.stack = stack;
+        self.rpl_flags.copy_from_slice(rpl_flags);
+        self.exited = exited;
+        self.rng.reseed(rng_state);
+        self.cycles_per_frame = cycles_per_frame;
+        self.beep_sound_flag = beep_sound_flag;
+        self.vblank = vblank;
+        self.key_wait = waiting_for_key.then_some(KeyWait {
+            register,
+            pressed: has_pressed.then_some(pressed),
+        });
+        self.clear_screen_flag = true;
+        self.update_screen_flag = true;
+        Ok(())
+    }
+
+    // Runs one 60 Hz frame: ticks the timers, then executes the instructions of the frame
+    // Screen flags report the changes made during the whole frame
+    pub fn run_frame(&mut self, keypad: &mut dyn Keypad) -> Result<(), Chip8Error> {
+        self.start_frame();
+        for _ in 0..self.cycles_per_frame {
+            self.step(keypad)?;
+            if self.exited {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Resets the screen flags and ticks the timers, for frontends executing the
+    // instructions of a frame one by one
+    pub fn start_frame(&mut self) {
+        self.update_screen_flag = false;
+        self.clear_screen_flag = false;
+        self.tick_timers();
+    }
+
+    // Decrements the timers, once per frame
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+            self.beep_sound_flag = self.sound_timer > 0;
+        }
+        self.vblank = true;
+        self.rng.tick();
+    }
+
+    pub fn get_cycles_per_frame(&self) -> u32 {
+        self.cycles_per_frame
+    }
+
+    // Executes a single instruction
+    pub fn step(&mut self, keypad: &mut dyn Keypad) -> Result<(), Chip8Error> {
+        // Handle key events, FX0A resumes once the pressed key is released
+        while let Some(event) = keypad.next_event() {
+            if let Some(key_wait) = self.key_wait.as_mut() {
+                match event {
+                    KeyEvent::Down(key) => key_wait.pressed = Some(key),
+                    KeyEvent::Up(key) if key_wait.pressed == Some(key) => {
+                        self.registers[key_wait.register] = key;
+                        self.key_wait = None;
+                    }
+                    KeyEvent::Up(_) => {}
+                }
+            }
+        }
+        self.memory_log.len = 0;
+        if self.key_wait.is_some() || self.exited {
+            return Ok(());
+        }
+
+        // Interpret instruction
+        let instruction_pc = self.program_counter;
+        if instruction_pc as usize + 1 >= self.memory_size {
+            return Err(Chip8Error::PcOutOfBounds(instruction_pc));
+        }
+
+        let instruction_num = self.read_word(instruction_pc as usize);
+        self.program_counter = self.program_counter.wrapping_add(2);
+
+        let opcode = match decode(instruction_num) {
+            Ok(opcode) => opcode,
+            Err(Unknown(opcode)) => {
+                let err = Chip8Error::UnknownOpcode {
+                    pc: instruction_pc,
+                    opcode,
+                };
+                // Traced like any other instruction, it is the last one of the trace
+                #[cfg(feature = "std")]
+                if let Some(tracer) = &self.tracer {
+                    let state = TraceState::of(self);
+                    tracer.borrow_mut().record(
+                        instruction_pc,
+                        instruction_num,
+                        None,
+                        &state,
+                        &state,
+                        Some(&err),
+                    );
+                }
+                return Err(err);
+            }
+        };
+
+        #[cfg(feature = "std")]
+        let before = self.tracer.as_ref().map(|_| TraceState::of(self));
+        let result = self.execute(opcode, instruction_pc, keypad);
+        #[cfg(feature = "std")]
+        if let (Some(tracer), Some(before)) = (&self.tracer, before) {
+            tracer.borrow_mut().record(
+                instruction_pc,
+                instruction_num,
+                Some(opcode),
+                &before,
+                &TraceState::of(self),
+                result.as_ref().err(),
+            );
+        }
+        result?;
+
+        #[cfg(feature = "std")]
+        if self.debug {
+            println!("{}", DebugData::new(self, Some(opcode)));
+        }
+        Ok(())
+    }
+
+    // Runs a decoded instruction, the program counter already points past it
+    fn execute(
+        &mut self,
+        opcode: Opcode,
+        instruction_pc: u16,
+        keypad: &mut dyn Keypad,
+    ) -> Result<(), Chip8Error> {
+        match opcode {
+            Opcode::Sys(_) => {
+                // Machine code routines are not emulated
+            }
+            Opcode::Cls => {
+                let planes = self.planes;
+                self.screen.iter_mut().for_each(|val| *val &= !planes);
+                self.clear_screen_flag = true;
+                self.update_screen_flag = true;
+            }
+            Opcode::Ret => {
+                if self.stack_pointer == 0 {
+                    return Err(Chip8Error::StackUnderflow(instruction_pc));
+                }
+                self.stack_pointer -= 1;
+                self.program_counter = self.stack[self.stack_pointer];
+            }
+            Opcode::ScrollDown(n) => self.scroll(0, n as isize),
+            Opcode::ScrollUp(n) => self.scroll(0, -(n as isize)),
+            Opcode::ScrollRight => self.scroll(4, 0),
+            Opcode::ScrollLeft => self.scroll(-4, 0),
+            Opcode::Exit => self.exited = true,
+            Opcode::Lores | Opcode::Hires => {
+                // A machine built with a CHIP-8 sized screen has no high resolution
+                if opcode == Opcode::Hires && SCREEN < constants::SCREEN_SIZE {
+                    return Err(Chip8Error::UnknownOpcode {
+                        pc: instruction_pc,
+                        opcode: 0x00FF,
+                    });
+                }
+                self.hires = opcode == Opcode::Hires;
+                self.screen.fill(0);
+                self.clear_screen_flag = true;
+                self.update_screen_flag = true;
+            }
+            Opcode::Jp(addr) => self.program_counter = addr,
+            Opcode::Call(addr) => {
+                if self.stack_pointer >= self.stack_depth {
+                    return Err(Chip8Error::StackOverflow(instruction_pc));
+                }
+                self.stack[self.stack_pointer] = self.program_counter;
+                self.stack_pointer += 1;
+                self.program_counter = addr;
+            }
+            Opcode::SeByte { x, byte } => self.skip_if(self.registers[x as usize] == byte),
+            Opcode::SneByte { x, byte } => self.skip_if(self.registers[x as usize] != byte),
+            Opcode::SeReg { x, y } => {
+                self.skip_if(self.registers[x as usize] == self.registers[y as usize])
+            }
+            // Save VX..VY, in reverse order when X > Y
+            Opcode::SaveRange { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let range = self.memory_range(instruction_pc, x.abs_diff(y) + 1)?;
+                for (offset, addr) in range.enumerate() {
+                    self.store(addr, self.registers[Self::register_at(x, y, offset)]);
+                }
+            }
+            // Load VX..VY, in reverse order when X > Y
+            Opcode::LoadRange { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let range = self.memory_range(instruction_pc, x.abs_diff(y) + 1)?;
+                for (offset, addr) in range.enumerate() {
+                    self.registers[Self::register_at(x, y, offset)] = self.load(addr);
+                }
+            }
+            Opcode::LdByte { x, byte } => self.registers[x as usize] = byte,
+            Opcode::AddByte { x, byte } => {
+                self.registers[x as usize] = self.registers[x as usize].wrapping_add(byte)
+            }
+            Opcode::LdReg { x, y } => self.registers[x as usize] = self.registers[y as usize],
+            Opcode::Or { x, y } | Opcode::And { x, y } | Opcode::Xor { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                match opcode {
+                    Opcode::Or { .. } => self.registers[x] |= self.registers[y],
+                    Opcode::And { .. } => self.registers[x] &= self.registers[y],
+                    _ => self.registers[x] ^= self.registers[y],
+                }
+                if self.quirks.logic_resets_vf {
+                    self.registers[0x0F] = 0;
+                }
+            }
+            Opcode::AddReg { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let result: usize = self.registers[x] as usize + self.registers[y] as usize;
+                if result > 255 {
+                    self.registers[0x0F] = 1;
+                } else {
+                    self.registers[0x0F] = 0;
+                }
+                self.registers[x] = result as u8;
+            }
+            Opcode::Sub { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                self.registers[0x0F] = if self.registers[x] > self.registers[y] {
+                    1
+                } else {
+                    0
+                };
+                self.registers[x] = (self.registers[x] as i32 - self.registers[y] as i32) as u8;
+            }
+            Opcode::Shr { x, y } => {
+                let source = self.shift_source(x as usize, y as usize);
+                self.registers[x as usize] = source >> 1;
+                self.registers[0x0F] = source & 0x01;
+            }
+            Opcode::Subn { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                self.registers[0x0F] = if self.registers[y] > self.registers[x] {
+                    1
+                } else {
+                    0
+                };
+                self.registers[x] = (self.registers[y] as i32 - self.registers[x] as i32) as u8;
+            }
+            Opcode::Shl { x, y } => {
+                let source = self.shift_source(x as usize, y as usize);
+                self.registers[x as usize] = source << 1;
+                self.registers[0x0F] = source >> 7;
+            }
+            Opcode::SneReg { x, y } => {
+                self.skip_if(self.registers[x as usize] != self.registers[y as usize])
+            }
+            Opcode::LdI(addr) => self.index_register = addr,
+            Opcode::JpV0(addr) => {
+                let offset_register = if self.quirks.jump_uses_vx {
+                    (addr >> 8) as usize
+                } else {
+                    0
+                };
+                self.program_counter = addr + self.registers[offset_register] as u16;
+            }
+            Opcode::Rnd { x, byte } => self.registers[x as usize] = byte & self.rng.next_u8(),
+            Opcode::Drw { .. } if self.quirks.display_wait && !self.vblank => {
+                // Wait for the next display refresh and draw again
+                self.program_counter = instruction_pc;
+            }
+            Opcode::Drw { x, y, n } => {
+                // DXY0 draws a 16x16 sprite, two bytes per row
+                let (sprite_width, rows) = if n == 0 { (16, 16) } else { (8, n as usize) };
+                let plane_size = rows * sprite_width / 8;
+                // Each selected plane reads its own sprite data, one after the other
+                let planes = self.planes;
+                let selected_planes = (0..constants::NUM_PLANES)
+                    .map(|plane| 1u8 << plane)
+                    .filter(move |plane| planes & plane > 0);
+                let sprite = self
+                    .memory_range(instruction_pc, plane_size * selected_planes.clone().count())?;
+                let (width, height) = (self.get_screen_width(), self.get_screen_height());
+                self.registers[0xF] = 0;
+                let xpos: usize = self.registers[x as usize] as usize % width;
+                let ypos: usize = self.registers[y as usize] as usize % height;
+                for (plane_index, plane) in selected_planes.enumerate() {
+                    let plane_start = sprite.start + plane_index * plane_size;
+                    for row in 0..rows {
+                        let current_y = match ypos + row {
+                            row_y if row_y < height => row_y,
+                            row_y if self.quirks.wrap_sprites => row_y % height,
+                            _ => break,
+                        };
+                        let bits: u16 = if sprite_width == 16 {
+                            (self.load(plane_start + row * 2) as u16) << 8
+                                | self.load(plane_start + row * 2 + 1) as u16
+                        } else {
+                            (self.load(plane_start + row) as u16) << 8
+                        };
+
+                        for col in 0..sprite_width {
+                            let current_x = match xpos + col {
+                                col_x if col_x < width => col_x,
+                                col_x if self.quirks.wrap_sprites => col_x % width,
+                                _ => break,
+                            };
+                            let pixel = current_y * width + current_x;
+                            let mask: u16 = 0x8000 >> col;
+
+                            if bits & mask > 0 {
+                                if self.screen[pixel] & plane > 0 {
+                                    self.registers[0x0F] = 1;
+                                }
+                                self.screen[pixel] ^= plane;
+                            }
+                        }
+                    }
+                }
+                self.vblank = false;
+                self.update_screen_flag = true;
+            }
+            Opcode::Skp(x) => self.skip_if(keypad.is_pressed(self.registers[x as usize])),
+            Opcode::Sknp(x) => self.skip_if(!keypad.is_pressed(self.registers[x as usize])),
+            // F000 NNNN loads a 16 bit address into I
+            Opcode::LdILong => {
+                if instruction_pc as usize + 3 >= self.memory_size {
+                    return Err(Chip8Error::PcOutOfBounds(instruction_pc));
+                }
+                self.index_register = self.read_word(self.program_counter as usize);
+                self.program_counter = self.program_counter.wrapping_add(2);
+            }
+            Opcode::Plane(x) => self.planes = x & 0x03,
+            Opcode::Audio => {
+                let range = self.memory_range(instruction_pc, constants::AUDIO_PATTERN_SIZE)?;
+                let mut pattern = [0; constants::AUDIO_PATTERN_SIZE];
+                for (offset, addr) in range.enumerate() {
+                    pattern[offset] = self.load(addr);
+                }
+                self.audio_pattern = Some(pattern);
+            }
+            Opcode::Pitch(x) => self.pitch = self.registers[x as usize],
+            Opcode::LdVxDt(x) => self.registers[x as usize] = self.delay_timer,
+            Opcode::LdKey(x) => {
+                self.key_wait = Some(KeyWait {
+                    register: x as usize,
+                    pressed: None,
+                })
+            }
+            Opcode::LdDt(x) => self.delay_timer = self.registers[x as usize],
+            Opcode::LdSt(x) => self.set_sound_timer(self.registers[x as usize]),
+            Opcode::AddI(x) => {
+                self.index_register = self
+                    .index_register
+                    .wrapping_add(self.registers[x as usize] as u16)
+            }
+            Opcode::LdF(x) => self.index_register = self.registers[x as usize] as u16 * 0x05,
+            Opcode::LdHf(x) => {
+                self.index_register = (constants::BIG_FONT_START
+                    + (self.registers[x as usize] & 0x0F) as usize * 10)
+                    as u16
+            }
+            Opcode::Bcd(x) => {
+                let num = self.registers[x as usize];
+                let h = num / 100;
+                let t = (num - h * 100) / 10;
+                let o = num - h * 100 - t * 10;
+                let i = self.memory_range(instruction_pc, 3)?.start;
+                self.store(i, h);
+                self.store(i + 1, t);
+                self.store(i + 2, o);
+            }
+            Opcode::Store(x) => {
+                let x = x as usize;
+                let range = self.memory_range(instruction_pc, x + 1)?;
+                for (offset, addr) in range.enumerate() {
+                    self.store(addr, self.registers[offset]);
+                }
+                self.increment_index_after_load_store(x);
+            }
+            Opcode::Load(x) => {
+                let x = x as usize;
+                let range = self.memory_range(instruction_pc, x + 1)?;
+                for (offset, addr) in range.enumerate() {
+                    self.registers[offset] = self.load(addr);
+                }
+                self.increment_index_after_load_store(x);
+            }
+            Opcode::SaveFlags(x) => {
+                let x = x as usize;
+                self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x])
+            }
+            Opcode::LoadFlags(x) => {
+                let x = x as usize;
+                self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x])
+            }
+        }
+        Ok(())
+    }
+
+    // Skips the next instruction when the condition holds
+    // The 4 bytes long F000 NNNN is skipped as a whole
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            let next = self.program_counter as usize;
+            let skip = if next + 1 < self.memory_size {
+                decode(self.read_word(next)).map_or(2, |opcode| opcode.size())
+            } else {
+                2
+            };
+            self.program_counter = self.program_counter.wrapping_add(skip as u16);
+        }
+    }
+
+    // Big endian word at the given address
+    fn read_word(&self, address: usize) -> u16 {
+        (self.memory[address] as u16) << 8 | self.memory[address + 1] as u16
+    }
+
+    // Memory reads and writes of instructions go through `load` and `store`, which record
+    // them when the memory log is enabled
+    fn load(&mut self, address: usize) -> u8 {
+        self.memory_log.push(MemoryAccess {
+            address,
+            write: false,
+        });
+        self.memory[address]
+    }
+
+    fn store(&mut self, address: usize, value: u8) {
+        self.memory_log.push(MemoryAccess {
+            address,
+            write: true,
+        });
+        self.memory[address] = value;
+    }
+
+    // Register at the given offset from X towards Y (5XY2/5XY3)
+    fn register_at(x: usize, y: usize, offset: usize) -> usize {
+        if x <= y {
+            x + offset
+        } else {
+            x - offset
+        }
+    }
+
+    // Scrolls the selected planes right by dx and down by dy pixels, negative amounts
+    // scroll left and up (00CN/00DN/00FB/00FC)
+    // Works in place: pixels are visited away from the direction of the scroll, so every
+    // source pixel is read before it gets overwritten
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.get_screen_width(), self.get_screen_height());
+        for row in 0..height {
+            let y = if dy > 0 { height - 1 - row } else { row };
+            for column in 0..width {
+                let x = if dx > 0 { width - 1 - column } else { column };
+                let source_x = x as isize - dx;
+                let source_y = y as isize - dy;
+                let source = if (0..width as isize).contains(&source_x)
+                    && (0..height as isize).contains(&source_y)
+                {
+                    self.screen[source_y as usize * width + source_x as usize]
+                } else {
+                    0
+                };
+                let pixel = y * width + x;
+                self.screen[pixel] = (self.screen[pixel] & !self.planes) | (source & self.planes);
+            }
+        }
+        self.update_screen_flag = true;
+    }
+
+    // Register shifted by 8XY6/8XYE
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        }
+    }
+
+    fn increment_index_after_load_store(&mut self, x: usize) {
+        if self.quirks.load_store_increments_i {
+            self.index_register = self.index_register.wrapping_add(x as u16 + 1);
+        }
+    }
+
+    // Memory addresses [I, I + len), checked against the memory size
+    fn memory_range(&self, pc: u16, len: usize) -> Result<Range<usize>, Chip8Error> {
+        let start = self.index_register as usize;
+        if start + len > self.memory_size {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                pc,
+                address: start + len - 1,
+            });
+        }
+        Ok(start..start + len)
+    }
+}
+
+#[cfg(feature = "std")]
+struct DebugData<'a, const MEMORY: usize, const SCREEN: usize> {
+    chip: &'a Chip8<MEMORY, SCREEN>,
+    instruction: Option<Opcode>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, const MEMORY: usize, const SCREEN: usize> DebugData<'a, MEMORY, SCREEN> {
+    fn new(chip: &'a Chip8<MEMORY, SCREEN>, instruction: Option<Opcode>) -> Self {
+        DebugData { chip, instruction }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const MEMORY: usize, const SCREEN: usize> std::fmt::Display for DebugData<'_, MEMORY, SCREEN> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(instr) = self.instruction {
+            writeln!(f, "Instruction: {}", instr).unwrap();
+        }
+        writeln!(f, "Registers: {:?}", self.chip.registers).unwrap();
+        writeln!(f, "Index Register: {}", self.chip.index_register).unwrap();
+        writeln!(f, "Program Counter: 0x{:02X}", self.chip.program_counter).unwrap();
+        writeln!(f, "Stack: {:03X?}", self.chip.get_stack()).unwrap();
+        writeln!(
+            f,
+            "Stack Pointer: {} / {}",
+            self.chip.stack_pointer, self.chip.stack_depth
+        )
+        .unwrap();
+        writeln!(f, "Clear Screen Flag: {}", self.chip.clear_screen_flag).unwrap();
+        writeln!(f, "Update Screen Flag: {}", self.chip.update_screen_flag).unwrap();
+        writeln!(f, "Should Beep: {}", self.chip.beep_sound_flag).unwrap();
+        writeln!(f, "Hires: {}", self.chip.hires).unwrap();
+        writeln!(f, "Waiting For Key: {}", self.chip.key_wait.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypad::KeyEvent;
+
+    struct NoKeys;
+
+    impl Keypad for NoKeys {
+        fn is_pressed(&self, _key: u8) -> bool {
+            false
+        }
+
+        fn next_event(&mut self) -> Option<KeyEvent> {
+            None
+        }
+    }
+
+    // Keypad delivering at most one scripted event to the next step
+    struct OneEvent(Option<KeyEvent>);
+
+    impl Keypad for OneEvent {
+        fn is_pressed(&self, _key: u8) -> bool {
+            false
+        }
+
+        fn next_event(&mut self) -> Option<KeyEvent> {
+            self.0.take()
+        }
+    }
+
+    // Steps once after the given key event
+    fn step_with(chip8: &mut Chip8, event: KeyEvent) {
+        chip8.step(&mut OneEvent(Some(event))).unwrap();
+    }
+
+    // V3 := key, then V0 := 1
+    const KEY_WAIT: [u8; 4] = [0xF3, 0x0A, 0x60, 0x01];
+
+    // CHIP-8 sized machine, 4K of memory and a 64x32 screen
+    type SmallChip8 = Chip8<{ constants::MEMORY_IN_B }, { constants::LORES_SCREEN_SIZE }>;
+
+    fn boot(rom: &[u8]) -> Chip8 {
+        let mut chip8: Chip8 = Chip8::empty();
+        chip8
+            .load_rom(rom, 8, constants::STACK_LEVELS, Quirks::default(), 1)
+            .unwrap();
+        chip8
+    }
+
+    // Every pixel set, with a pattern that tells the planes and positions apart
+    fn fill_screen(chip8: &mut Chip8) {
+        for (pixel, value) in chip8.screen.iter_mut().enumerate() {
+            *value = (pixel % 7 % 4) as u8;
+        }
+    }
+
+    // Scrolls both in place and into a copy of the screen, which must agree
+    fn assert_scrolls(hires: bool, planes: u8, dx: isize, dy: isize) {
+        let mut chip8 = boot(&[]);
+        chip8.hires = hires;
+        chip8.planes = planes;
+        fill_screen(&mut chip8);
+        let previous = chip8.screen;
+        chip8.scroll(dx, dy);
+
+        let (width, height) = (chip8.get_screen_width(), chip8.get_screen_height());
+        for y in 0..height {
+            for x in 0..width {
+                let source_x = x as isize - dx;
+                let source_y = y as isize - dy;
+                let source = if (0..width as isize).contains(&source_x)
+                    && (0..height as isize).contains(&source_y)
+                {
+                    previous[source_y as usize * width + source_x as usize]
+                } else {
+                    0
+                };
+                let pixel = y * width + x;
+                let expected = (previous[pixel] & !planes) | (source & planes);
+                assert_eq!(
+                    chip8.screen[pixel], expected,
+                    "pixel ({}, {}) scrolling by ({}, {}) on planes {}",
+                    x, y, dx, dy, planes
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sound_timer_beeps_as_soon_as_it_is_set() {
+        // V0 := 1, ST := V0
+        let mut chip8 = boot(&[0x60, 0x01, 0xF0, 0x18]);
+        chip8.step(&mut NoKeys).unwrap();
+        chip8.step(&mut NoKeys).unwrap();
+        assert!(chip8.should_beep());
+        chip8.tick_timers();
+        assert!(!chip8.should_beep());
+    }
+
+    #[test]
+    fn scrolls_in_place_in_every_direction() {
+        for hires in [false, true] {
+            for planes in 1..=3 {
+                assert_scrolls(hires, planes, 0, 3);
+                assert_scrolls(hires, planes, 0, -3);
+                assert_scrolls(hires, planes, 4, 0);
+                assert_scrolls(hires, planes, -4, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn scrolling_leaves_other_planes_alone() {
+        let mut chip8 = boot(&[]);
+        chip8.planes = 0x02;
+        fill_screen(&mut chip8);
+        let previous = chip8.screen;
+        chip8.scroll(0, 40);
+        assert!(chip8.screen[..constants::LORES_SCREEN_SIZE]
+            .iter()
+            .zip(previous)
+            .all(|(&pixel, previous)| pixel == previous & 0x01));
+    }
+
+    #[test]
+    fn boots_in_place_and_resets_on_load() {
+        let mut chip8: Chip8 = Chip8::empty();
+        // V0 := 0x42, clear the screen with high resolution
+        chip8
+            .load_rom(&[0x60, 0x42, 0x00, 0xFF], 8, 4, Quirks::xochip(), 1)
+            .unwrap();
+        assert_eq!(chip8.get_memory().len(), constants::XO_CHIP_MEMORY_IN_B);
+        assert_eq!(chip8.get_memory()[..5], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert_eq!(chip8.get_memory()[constants::BIG_FONT_START], 0xFF);
+        assert_eq!(chip8.get_memory()[0x200..0x204], [0x60, 0x42, 0x00, 0xFF]);
+        assert_eq!(chip8.get_stack_depth(), 4);
+        chip8.step(&mut NoKeys).unwrap();
+        chip8.step(&mut NoKeys).unwrap();
+        assert_eq!(chip8.get_registers()[0], 0x42);
+        assert_eq!(chip8.get_screen_width(), constants::HIRES_SCREEN_WIDTH);
+
+        chip8
+            .load_rom(&[0x12, 0x00], 8, 4, Quirks::default(), 1)
+            .unwrap();
+        assert_eq!(chip8.get_registers()[0], 0);
+        assert_eq!(chip8.get_program_counter(), 0x200);
+        assert_eq!(chip8.get_screen_width(), constants::SCREEN_WIDTH);
+        assert_eq!(chip8.get_memory().len(), constants::MEMORY_IN_B);
+        assert_eq!(chip8.get_memory()[0x202], 0);
+    }
+
+    #[test]
+    fn rejected_roms_leave_the_machine_untouched() {
+        let mut chip8 = boot(&[0x60, 0x42]);
+        chip8.step(&mut NoKeys).unwrap();
+        let rom = [0; constants::MEMORY_IN_B - constants::PROGRAM_MEMORY_START + 1];
+        assert_eq!(
+            chip8.load_rom(&rom, 8, 4, Quirks::default(), 1),
+            Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max_size: rom.len() - 1
+            })
+        );
+        assert_eq!(chip8.get_registers()[0], 0x42);
+        assert_eq!(chip8.get_program_counter(), 0x202);
+    }
+
+    #[test]
+    fn small_machine_runs_chip8_programs() {
+        let mut chip8 = SmallChip8::empty();
+        assert_eq!(
+            chip8.load_rom(&[0x12, 0x00], 8, 4, Quirks::xochip(), 1),
+            Err(Chip8Error::MemoryTooSmall {
+                size: constants::XO_CHIP_MEMORY_IN_B,
+                max_size: constants::MEMORY_IN_B
+            })
+        );
+
+        // Draw the 0 digit at (0, 0), then switch to high resolution
+        let rom = [0xD0, 0x05, 0x00, 0xFF];
+        chip8.load_rom(&rom, 8, 4, Quirks::default(), 1).unwrap();
+        assert_eq!(chip8.get_memory().len(), constants::MEMORY_IN_B);
+        chip8.step(&mut NoKeys).unwrap();
+        assert_eq!(chip8.get_screen().len(), constants::LORES_SCREEN_SIZE);
+        assert_eq!(chip8.get_screen()[..4], [1, 1, 1, 1]);
+        assert_eq!(
+            chip8.step(&mut NoKeys),
+            Err(Chip8Error::UnknownOpcode {
+                pc: 0x202,
+                opcode: 0x00FF
+            })
+        );
+        assert_eq!(chip8.get_screen_width(), constants::SCREEN_WIDTH);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn states_move_between_machine_sizes() {
+        // Draw the 0 digit at (0, 0)
+        let mut chip8 = boot(&[0xD0, 0x05]);
+        chip8.step(&mut NoKeys).unwrap();
+        let state = chip8.save_state();
+
+        let mut small = SmallChip8::empty();
+        small.load_rom(&[], 8, 4, Quirks::default(), 1).unwrap();
+        small.load_state(&state).unwrap();
+        assert_eq!(small.get_screen(), chip8.get_screen());
+        assert_eq!(small.get_program_counter(), 0x202);
+        assert_eq!(small.save_state(), state);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn states_that_do_not_fit_leave_the_machine_untouched() {
+        let mut small = SmallChip8::empty();
+        small
+            .load_rom(&[0x60, 0x42], 8, 4, Quirks::default(), 1)
+            .unwrap();
+        small.step(&mut NoKeys).unwrap();
+
+        let mut hires = boot(&[0x00, 0xFF]);
+        hires.step(&mut NoKeys).unwrap();
+        let mut xochip: Chip8 = Chip8::empty();
+        xochip.load_rom(&[], 8, 4, Quirks::xochip(), 1).unwrap();
+        for (state, reason) in [
+            (hires.save_state(), "screen does not fit the machine"),
+            (xochip.save_state(), "memory does not fit the machine"),
+            (small.save_state()[..100].to_vec(), "truncated file"),
+        ] {
+            assert_eq!(
+                small.load_state(&state),
+                Err(Chip8Error::InvalidState(reason.to_string()))
+            );
+            assert_eq!(small.get_registers()[0], 0x42);
+            assert_eq!(small.get_program_counter(), 0x202);
+        }
+    }
+
+    #[test]
+    fn timers_keep_running_while_waiting_for_a_key() {
+        // V0 := 5, DT := V0, ST := V0, V3 := key
+        let mut chip8 = boot(&[0x60, 0x05, 0xF0, 0x15, 0xF0, 0x18, 0xF3, 0x0A]);
+        for _ in 0..4 {
+            chip8.step(&mut NoKeys).unwrap();
+        }
+        assert!(chip8.is_waiting_for_key());
+        chip8.run_frame(&mut NoKeys).unwrap();
+        chip8.run_frame(&mut NoKeys).unwrap();
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.get_program_counter(), 0x208);
+        assert_eq!(chip8.get_delay_timer(), 3);
+        assert_eq!(chip8.get_sound_timer(), 3);
+    }
+
+    #[test]
+    fn key_wait_does_not_resume_on_press() {
+        let mut chip8 = boot(&KEY_WAIT);
+        chip8.step(&mut NoKeys).unwrap();
+        step_with(&mut chip8, KeyEvent::Down(0x5));
+        step_with(&mut chip8, KeyEvent::Down(0x6));
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.get_registers()[3], 0);
+        assert_eq!(chip8.get_program_counter(), 0x202);
+    }
+
+    #[test]
+    fn key_wait_resumes_on_release_of_the_pressed_key() {
+        let mut chip8 = boot(&KEY_WAIT);
+        chip8.step(&mut NoKeys).unwrap();
+        step_with(&mut chip8, KeyEvent::Down(0x5));
+        step_with(&mut chip8, KeyEvent::Up(0x5));
+        assert!(!chip8.is_waiting_for_key());
+        assert_eq!(chip8.get_registers()[3], 0x5);
+        // The release step runs the next instruction too
+        assert_eq!(chip8.get_registers()[0], 1);
+    }
+
+    #[test]
+    fn key_wait_ignores_the_release_of_another_key() {
+        let mut chip8 = boot(&KEY_WAIT);
+        chip8.step(&mut NoKeys).unwrap();
+        step_with(&mut chip8, KeyEvent::Down(0x5));
+        step_with(&mut chip8, KeyEvent::Up(0x6));
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.get_registers()[3], 0);
+        // The last key pressed is the one waited for
+        step_with(&mut chip8, KeyEvent::Down(0x6));
+        step_with(&mut chip8, KeyEvent::Up(0x5));
+        assert!(chip8.is_waiting_for_key());
+        step_with(&mut chip8, KeyEvent::Up(0x6));
+        assert_eq!(chip8.get_registers()[3], 0x6);
+    }
+
+    #[test]
+    fn key_held_before_the_wait_does_not_satisfy_it() {
+        let mut chip8 = boot(&KEY_WAIT);
+        // Pressed as FX0A executes, so before the wait starts
+        step_with(&mut chip8, KeyEvent::Down(0x5));
+        assert!(chip8.is_waiting_for_key());
+        step_with(&mut chip8, KeyEvent::Up(0x5));
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.get_registers()[3], 0);
+        step_with(&mut chip8, KeyEvent::Down(0x5));
+        step_with(&mut chip8, KeyEvent::Up(0x5));
+        assert_eq!(chip8.get_registers()[3], 0x5);
+    }
+}
@@ -0,0 +1,24 @@
+pub const MEMORY_IN_B: usize = 4096;
+pub const XO_CHIP_MEMORY_IN_B: usize = 65536;
+pub const NUM_REGISTERS: usize = 16;
+pub const NUM_KEYS: usize = 16;
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+pub const SCREEN_SIZE: usize = HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT;
+pub const LORES_SCREEN_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+pub const BIG_FONT_START: usize = 0x50;
+pub const NUM_RPL_FLAGS: usize = 16;
+pub const NUM_PLANES: usize = 2;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+pub const DEFAULT_PITCH: u8 = 64;
+pub const STACK_LEVELS: usize = 16;
+pub const MAX_STACK_LEVELS: usize = 64;
+pub const PROGRAM_MEMORY_START: usize = 0x200;
+pub const VIP_PAGE_SIZE: usize = 0x100;
+// Most memory bytes a single instruction accesses, a 16x16 sprite drawn on both planes
+pub const MAX_MEMORY_ACCESSES: usize = 64;
+// Rate of the delay and sound timers, hosts call `Chip8::run_frame` this many times per
+// second
+pub const FRAMES_PER_SECOND: u128 = 60;
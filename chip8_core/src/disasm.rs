@@ -0,0 +1,477 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::constants;
+use crate::opcode::{decode, Opcode};
+
+// Mnemonics of the disassembly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    // Cowgod's technical reference, e.g. `LD V1, 0x20`
+    Cowgod,
+    // Octo assembly language, e.g. `v1 := 0x20`
+    Octo,
+}
+
+impl Syntax {
+    pub const NAMES: [&'static str; 2] = ["cowgod", "octo"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cowgod" => Some(Syntax::Cowgod),
+            "octo" => Some(Syntax::Octo),
+            _ => None,
+        }
+    }
+}
+
+// Part of the ROM printed on one line
+enum Line {
+    Code { address: u16, opcode: Opcode },
+    Data { address: u16, len: usize },
+}
+
+// Kind of a label, in increasing order of precedence
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Jump,
+    Sub,
+}
+
+// Most data bytes printed on one line
+const DATA_PER_LINE: usize = 8;
+
+// Disassembles a ROM loaded at `PROGRAM_MEMORY_START`, one line per instruction with its
+// address, raw bytes and mnemonic
+// The recursive descent mode follows jumps and calls from the entry point, so only
+// reachable instructions are decoded and everything else is printed as data
+pub fn disassemble(rom: &[u8], recursive: bool, syntax: Syntax) -> String {
+    let lines = if recursive {
+        recursive_lines(rom)
+    } else {
+        linear_lines(rom)
+    };
+    let labels = labels(rom, &lines);
+
+    let mut out = String::new();
+    for line in &lines {
+        let (address, len) = match *line {
+            Line::Code { address, opcode } => (address, opcode.size()),
+            Line::Data { address, len } => (address, len),
+        };
+        if let Some(label) = labels.get(&address) {
+            match syntax {
+                Syntax::Cowgod => out.push_str(&format!("{}:\n", label)),
+                Syntax::Octo => out.push_str(&format!(": {}\n", label)),
+            }
+        }
+
+        let offset = address as usize - constants::PROGRAM_MEMORY_START;
+        let bytes = &rom[offset..offset + len];
+        let raw = bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let text = match *line {
+            Line::Code { opcode, .. } => mnemonic(opcode, bytes, syntax, &labels),
+            Line::Data { .. } => data(bytes, syntax),
+        };
+        out.push_str(&format!(
+            "0x{:03X}  {:<width$}  {}\n",
+            address,
+            raw,
+            text,
+            width = DATA_PER_LINE * 3 - 1
+        ));
+    }
+    out
+}
+
+// Decodes every word from the start of the ROM
+fn linear_lines(rom: &[u8]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = (constants::PROGRAM_MEMORY_START + offset) as u16;
+        match opcode_at(rom, offset) {
+            Some(opcode) => {
+                lines.push(Line::Code { address, opcode });
+                offset += opcode.size();
+            }
+            None => {
+                let len = (rom.len() - offset).min(2);
+                lines.push(Line::Data { address, len });
+                offset += len;
+            }
+        }
+    }
+    lines
+}
+
+// Decodes the instructions reachable from the entry point
+fn recursive_lines(rom: &[u8]) -> Vec<Line> {
+    let mut code = vec![false; rom.len()];
+    let mut starts = BTreeMap::new();
+    let mut pending = vec![0];
+
+    while let Some(mut offset) = pending.pop() {
+        while let Some(opcode) = opcode_at(rom, offset) {
+            if code[offset..offset + opcode.size()]
+                .iter()
+                .any(|&byte| byte)
+            {
+                break;
+            }
+            code[offset..offset + opcode.size()]
+                .iter_mut()
+                .for_each(|byte| *byte = true);
+            starts.insert(offset, opcode);
+
+            let next = offset + opcode.size();
+            match opcode {
+                Opcode::Jp(addr) => {
+                    pending.extend(rom_offset(rom, addr));
+                    break;
+                }
+                Opcode::Call(addr) => pending.extend(rom_offset(rom, addr)),
+                // Computed jumps can't be followed
+                Opcode::Ret | Opcode::Exit | Opcode::JpV0(_) => break,
+                // Skips continue after the next instruction too
+                Opcode::SeByte { .. }
+                | Opcode::SneByte { .. }
+                | Opcode::SeReg { .. }
+                | Opcode::SneReg { .. }
+                | Opcode::Skp(_)
+                | Opcode::Sknp(_) => {
+                    if let Some(skipped) = opcode_at(rom, next) {
+                        pending.push(next + skipped.size());
+                    }
+                }
+                _ => {}
+            }
+            offset = next;
+        }
+    }
+
+    // Data lines are split where I points to, so those addresses get a label
+    let data_targets: BTreeSet<usize> = starts
+        .iter()
+        .filter_map(|(&offset, &opcode)| match opcode {
+            Opcode::LdI(addr) => rom_offset(rom, addr),
+            Opcode::LdILong => rom_offset(rom, read_word(rom, offset + 2)),
+            _ => None,
+        })
+        .collect();
+
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = (constants::PROGRAM_MEMORY_START + offset) as u16;
+        if let Some(&opcode) = starts.get(&offset) {
+            lines.push(Line::Code { address, opcode });
+            offset += opcode.size();
+        } else {
+            let len = 1 + code[offset + 1..]
+                .iter()
+                .enumerate()
+                .take(DATA_PER_LINE - 1)
+                .take_while(|&(index, &byte)| {
+                    !byte && !data_targets.contains(&(offset + 1 + index))
+                })
+                .count();
+            lines.push(Line::Data { address, len });
+            offset += len;
+        }
+    }
+    lines
+}
+
+// Instruction at the given ROM offset, if it decodes and fits in the ROM
+fn opcode_at(rom: &[u8], offset: usize) -> Option<Opcode> {
+    if offset + 1 >= rom.len() {
+        return None;
+    }
+    decode(read_word(rom, offset))
+        .ok()
+        .filter(|opcode| offset + opcode.size() <= rom.len())
+}
+
+// Big endian word at the given ROM offset
+fn read_word(rom: &[u8], offset: usize) -> u16 {
+    (rom[offset] as u16) << 8 | rom[offset + 1] as u16
+}
+
+fn rom_offset(rom: &[u8], address: u16) -> Option<usize> {
+    (address as usize)
+        .checked_sub(constants::PROGRAM_MEMORY_START)
+        .filter(|&offset| offset < rom.len())
+}
+
+// Names the addresses referenced by jumps, calls and I, when a line starts there
+fn labels(rom: &[u8], lines: &[Line]) -> BTreeMap<u16, String> {
+    let line_starts: BTreeSet<u16> = lines
+        .iter()
+        .map(|line| match *line {
+            Line::Code { address, .. } | Line::Data { address, .. } => address,
+        })
+        .collect();
+
+    let mut kinds: BTreeMap<u16, LabelKind> = BTreeMap::new();
+    for line in lines {
+        if let Line::Code { address, opcode } = *line {
+            let target = match opcode {
+                Opcode::Jp(addr) | Opcode::JpV0(addr) => Some((addr, LabelKind::Jump)),
+                Opcode::Call(addr) => Some((addr, LabelKind::Sub)),
+                Opcode::LdI(addr) => Some((addr, LabelKind::Data)),
+                Opcode::LdILong => {
+                    let offset = address as usize - constants::PROGRAM_MEMORY_START;
+                    Some((read_word(rom, offset + 2), LabelKind::Data))
+                }
+                _ => None,
+            };
+            if let Some((addr, kind)) = target.filter(|(addr, _)| line_starts.contains(addr)) {
+                let entry = kinds.entry(addr).or_insert(kind);
+                *entry = (*entry).max(kind);
+            }
+        }
+    }
+
+    kinds
+        .into_iter()
+        .map(|(addr, kind)| {
+            let prefix = match kind {
+                LabelKind::Data => "data",
+                LabelKind::Jump => "label",
+                LabelKind::Sub => "sub",
+            };
+            (addr, format!("{}_{:03X}", prefix, addr))
+        })
+        .collect()
+}
+
+fn mnemonic(
+    opcode: Opcode,
+    bytes: &[u8],
+    syntax: Syntax,
+    labels: &BTreeMap<u16, String>,
+) -> String {
+    let target = |addr: u16| {
+        labels
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| format!("0x{:03X}", addr))
+    };
+    let long_target = || target(read_word(bytes, 2));
+
+    match syntax {
+        Syntax::Cowgod => match opcode {
+            Opcode::Jp(addr) => format!("JP {}", target(addr)),
+            Opcode::Call(addr) => format!("CALL {}", target(addr)),
+            Opcode::LdI(addr) => format!("LD I, {}", target(addr)),
+            Opcode::JpV0(addr) => format!("JP V0, {}", target(addr)),
+            Opcode::LdILong => format!("LD I, LONG {}", long_target()),
+            _ => opcode.to_string(),
+        },
+        Syntax::Octo => match opcode {
+            Opcode::Sys(_) => format!("0x{:02X} 0x{:02X}", bytes[0], bytes[1]),
+            Opcode::Cls => "clear".to_string(),
+            Opcode::Ret => "return".to_string(),
+            Opcode::ScrollDown(n) => format!("scroll-down {}", n),
+            Opcode::ScrollUp(n) => format!("scroll-up {}", n),
+            Opcode::ScrollRight => "scroll-right".to_string(),
+            Opcode::ScrollLeft => "scroll-left".to_string(),
+            Opcode::Exit => "exit".to_string(),
+            Opcode::Lores => "lores".to_string(),
+            Opcode::Hires => "hires".to_string(),
+            Opcode::Jp(addr) => format!("jump {}", target(addr)),
+            // Subroutines are called by their bare name
+            Opcode::Call(addr) => match labels.get(&addr) {
+                Some(label) => label.clone(),
+                None => format!(":call 0x{:03X}", addr),
+            },
+            Opcode::SeByte { x, byte } => format!("if v{:x} != 0x{:02X} then", x, byte),
+            Opcode::SneByte { x, byte } => format!("if v{:x} == 0x{:02X} then", x, byte),
+            Opcode::SeReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            Opcode::SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            Opcode::LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            Opcode::LdByte { x, byte } => format!("v{:x} := 0x{:02X}", x, byte),
+            Opcode::AddByte { x, byte } => format!("v{:x} += 0x{:02X}", x, byte),
+            Opcode::LdReg { x, y } => format!("v{:x} := v{:x}", x, y),
+            Opcode::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            Opcode::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Opcode::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Opcode::AddReg { x, y } => format!("v{:x} += v{:x}", x, y),
+            Opcode::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            Opcode::Shr { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            Opcode::Subn { x, y } => format!("v{:x} =- v{:x}", x, y),
+            Opcode::Shl { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            Opcode::SneReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            Opcode::LdI(addr) => format!("i := {}", target(addr)),
+            Opcode::JpV0(addr) => format!("jump0 {}", target(addr)),
+            Opcode::Rnd { x, byte } => format!("v{:x} := random 0x{:02X}", x, byte),
+            Opcode::Drw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Opcode::Skp(x) => format!("if v{:x} -key then", x),
+            Opcode::Sknp(x) => format!("if v{:x} key then", x),
+            Opcode::LdILong => format!("i := long {}", long_target()),
+            Opcode::Plane(x) => format!("plane {}", x),
+            Opcode::Audio => "audio".to_string(),
+            Opcode::LdVxDt(x) => format!("v{:x} := delay", x),
+            Opcode::LdKey(x) => format!("v{:x} := key", x),
+            Opcode::LdDt(x) => format!("delay := v{:x}", x),
+            Opcode::LdSt(x) => format!("buzzer := v{:x}", x),
+            Opcode::AddI(x) => format!("i += v{:x}", x),
+            Opcode::LdF(x) => format!("i := hex v{:x}", x),
+            Opcode::LdHf(x) => format!("i := bighex v{:x}", x),
+            Opcode::Bcd(x) => format!("bcd v{:x}", x),
+            Opcode::Pitch(x) => format!("pitch := v{:x}", x),
+            Opcode::Store(x) => format!("save v{:x}", x),
+            Opcode::Load(x) => format!("load v{:x}", x),
+            Opcode::SaveFlags(x) => format!("saveflags v{:x}", x),
+            Opcode::LoadFlags(x) => format!("loadflags v{:x}", x),
+        },
+    }
+}
+
+fn data(bytes: &[u8], syntax: Syntax) -> String {
+    let values: Vec<String> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+    match syntax {
+        Syntax::Cowgod => format!("DB {}", values.join(", ")),
+        Syntax::Octo => values.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CALL and LD I point past an unreachable ADD, at a subroutine and a 4x5 sprite
+    const ROM: [u8; 19] = [
+        0x22, 0x0A, 0xA2, 0x0E, 0xD0, 0x15, 0x12, 0x06, 0x70, 0x01, 0x60, 0x05, 0x00, 0xEE, 0xF0,
+        0x90, 0x90, 0x90, 0xF0,
+    ];
+
+    fn assert_lines(rom: &[u8], recursive: bool, syntax: Syntax, expected: &[&str]) {
+        let out = disassemble(rom, recursive, syntax);
+        assert_eq!(out.lines().collect::<Vec<&str>>(), expected);
+    }
+
+    #[test]
+    fn linear_mode_decodes_every_word() {
+        assert_lines(
+            &ROM,
+            false,
+            Syntax::Cowgod,
+            &[
+                "0x200  22 0A                    CALL sub_20A",
+                "0x202  A2 0E                    LD I, data_20E",
+                "0x204  D0 15                    DRW V0, V1, 5",
+                "label_206:",
+                "0x206  12 06                    JP label_206",
+                "0x208  70 01                    ADD V0, 0x01",
+                "sub_20A:",
+                "0x20A  60 05                    LD V0, 0x05",
+                "0x20C  00 EE                    RET",
+                "data_20E:",
+                "0x20E  F0 90                    DB 0xF0, 0x90",
+                "0x210  90 90                    SNE V0, V9",
+                "0x212  F0                       DB 0xF0",
+            ],
+        );
+        assert_lines(
+            &ROM,
+            false,
+            Syntax::Octo,
+            &[
+                "0x200  22 0A                    sub_20A",
+                "0x202  A2 0E                    i := data_20E",
+                "0x204  D0 15                    sprite v0 v1 5",
+                ": label_206",
+                "0x206  12 06                    jump label_206",
+                "0x208  70 01                    v0 += 0x01",
+                ": sub_20A",
+                "0x20A  60 05                    v0 := 0x05",
+                "0x20C  00 EE                    return",
+                ": data_20E",
+                "0x20E  F0 90                    0xF0 0x90",
+                "0x210  90 90                    if v0 == v9 then",
+                "0x212  F0                       0xF0",
+            ],
+        );
+    }
+
+    #[test]
+    fn recursive_mode_separates_code_from_data() {
+        assert_lines(
+            &ROM,
+            true,
+            Syntax::Cowgod,
+            &[
+                "0x200  22 0A                    CALL sub_20A",
+                "0x202  A2 0E                    LD I, data_20E",
+                "0x204  D0 15                    DRW V0, V1, 5",
+                "label_206:",
+                "0x206  12 06                    JP label_206",
+                "0x208  70 01                    DB 0x70, 0x01",
+                "sub_20A:",
+                "0x20A  60 05                    LD V0, 0x05",
+                "0x20C  00 EE                    RET",
+                "data_20E:",
+                "0x20E  F0 90 90 90 F0           DB 0xF0, 0x90, 0x90, 0x90, 0xF0",
+            ],
+        );
+        assert_lines(
+            &ROM,
+            true,
+            Syntax::Octo,
+            &[
+                "0x200  22 0A                    sub_20A",
+                "0x202  A2 0E                    i := data_20E",
+                "0x204  D0 15                    sprite v0 v1 5",
+                ": label_206",
+                "0x206  12 06                    jump label_206",
+                "0x208  70 01                    0x70 0x01",
+                ": sub_20A",
+                "0x20A  60 05                    v0 := 0x05",
+                "0x20C  00 EE                    return",
+                ": data_20E",
+                "0x20E  F0 90 90 90 F0           0xF0 0x90 0x90 0x90 0xF0",
+            ],
+        );
+    }
+
+    #[test]
+    fn recursive_mode_follows_skips_and_splits_data_at_i() {
+        // SE V0, 0 then both a jump and LD I with EXIT, and 11 data bytes
+        let rom = [
+            0x30, 0x00, 0x12, 0x08, 0xA2, 0x0E, 0x00, 0xFD, 0xA2, 0x0C, 0x00, 0xFD, 0x11, 0x22,
+            0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB,
+        ];
+        assert_lines(
+            &rom,
+            true,
+            Syntax::Cowgod,
+            &[
+                "0x200  30 00                    SE V0, 0x00",
+                "0x202  12 08                    JP label_208",
+                "0x204  A2 0E                    LD I, data_20E",
+                "0x206  00 FD                    EXIT",
+                "label_208:",
+                "0x208  A2 0C                    LD I, data_20C",
+                "0x20A  00 FD                    EXIT",
+                "data_20C:",
+                "0x20C  11 22                    DB 0x11, 0x22",
+                "data_20E:",
+                "0x20E  33 44 55 66 77 88 99 AA  DB 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA",
+                "0x216  BB                       DB 0xBB",
+            ],
+        );
+    }
+
+    #[test]
+    fn parses_syntax_names() {
+        assert_eq!(Syntax::from_name("cowgod"), Some(Syntax::Cowgod));
+        assert_eq!(Syntax::from_name("octo"), Some(Syntax::Octo));
+        assert_eq!(Syntax::from_name("intel"), None);
+    }
+}
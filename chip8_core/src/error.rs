@@ -0,0 +1,121 @@
+use core::fmt;
+
+// Errors raised while loading a ROM or executing it
+// More variants may come with new features, matches need a wildcard arm
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Chip8Error {
+    // ROM file could not be opened or read
+    #[cfg(feature = "std")]
+    RomNotFound(String),
+    // ROM does not fit in program memory
+    RomTooLarge {
+        size: usize,
+        max_size: usize,
+    },
+    // Quirks need more memory than the machine was built with
+    MemoryTooSmall {
+        size: usize,
+        max_size: usize,
+    },
+    // Program counter left the addressable memory
+    PcOutOfBounds(u16),
+    // Subroutine call with every stack level in use
+    StackOverflow(u16),
+    // Subroutine return with an empty stack
+    StackUnderflow(u16),
+    // Memory access through the index register outside the addressable memory
+    MemoryOutOfBounds {
+        pc: u16,
+        address: usize,
+    },
+    // Instruction not part of the instruction set
+    UnknownOpcode {
+        pc: u16,
+        opcode: u16,
+    },
+    // Save state could not be read or restored
+    #[cfg(feature = "std")]
+    InvalidState(String),
+    // Movie could not be read, or was recorded with another ROM or settings
+    #[cfg(feature = "std")]
+    InvalidMovie(String),
+    // COSMAC VIP interpreter dump could not be read or has the wrong size
+    #[cfg(feature = "std")]
+    InvalidInterpreter(String),
+    // Window, audio device or terminal of a frontend failed
+    #[cfg(feature = "std")]
+    Frontend(String),
+}
+
+impl Chip8Error {
+    // Process exit code reported for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            #[cfg(feature = "std")]
+            Chip8Error::RomNotFound(_) => 2,
+            Chip8Error::RomTooLarge { .. } => 3,
+            Chip8Error::PcOutOfBounds(_) => 4,
+            Chip8Error::MemoryOutOfBounds { .. } => 5,
+            Chip8Error::UnknownOpcode { .. } => 6,
+            Chip8Error::StackOverflow(_) => 7,
+            Chip8Error::StackUnderflow(_) => 8,
+            #[cfg(feature = "std")]
+            Chip8Error::InvalidState(_) => 9,
+            #[cfg(feature = "std")]
+            Chip8Error::InvalidMovie(_) => 10,
+            #[cfg(feature = "std")]
+            Chip8Error::InvalidInterpreter(_) => 11,
+            Chip8Error::MemoryTooSmall { .. } => 12,
+            #[cfg(feature = "std")]
+            Chip8Error::Frontend(_) => 13,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Chip8Error::RomNotFound(path) => write!(f, "Could not read ROM file {}", path),
+            Chip8Error::RomTooLarge { size, max_size } => write!(
+                f,
+                "ROM is too large: {} bytes, at most {} bytes fit in memory",
+                size, max_size
+            ),
+            Chip8Error::MemoryTooSmall { size, max_size } => write!(
+                f,
+                "Quirks need {} bytes of memory, the machine has {} bytes",
+                size, max_size
+            ),
+            Chip8Error::PcOutOfBounds(pc) => {
+                write!(f, "Program counter out of memory bounds: 0x{:03X}", pc)
+            }
+            Chip8Error::StackOverflow(pc) => write!(f, "Stack overflow at 0x{:03X}", pc),
+            Chip8Error::StackUnderflow(pc) => {
+                write!(f, "Return with an empty stack at 0x{:03X}", pc)
+            }
+            Chip8Error::MemoryOutOfBounds { pc, address } => write!(
+                f,
+                "Memory access out of bounds at 0x{:03X}: address 0x{:03X}",
+                pc, address
+            ),
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode 0x{:04X} at 0x{:03X}", opcode, pc)
+            }
+            #[cfg(feature = "std")]
+            Chip8Error::InvalidState(reason) => write!(f, "Invalid save state: {}", reason),
+            #[cfg(feature = "std")]
+            Chip8Error::InvalidMovie(reason) => write!(f, "Invalid movie: {}", reason),
+            #[cfg(feature = "std")]
+            Chip8Error::InvalidInterpreter(reason) => {
+                write!(f, "Invalid VIP interpreter: {}", reason)
+            }
+            #[cfg(feature = "std")]
+            Chip8Error::Frontend(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Chip8Error {}
@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::fs;
+
+use crate::chip8;
+use crate::constants;
+use crate::error::Chip8Error;
+use crate::keypad::{KeyEvent, Keypad};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::state;
+use crate::trace::Tracer;
+
+// How long the headless run lasts
+#[derive(Clone, Copy)]
+pub enum RunLength {
+    Cycles(u64),
+    Frames(u64),
+}
+
+// Keypad driven by a key script instead of a keyboard
+// Each script line is `<frame> <down|up> <key>`, with the key in hex, e.g. `120 down A`
+// Empty lines and lines starting with `#` are ignored
+pub struct ScriptedKeypad {
+    script: VecDeque<(u64, KeyEvent)>,
+    pressed: [bool; constants::NUM_KEYS],
+    events: VecDeque<KeyEvent>,
+}
+
+impl ScriptedKeypad {
+    pub fn new(script: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (line_num, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = Self::parse_line(line)
+                .ok_or_else(|| format!("Invalid key script line {}: `{}`", line_num + 1, line))?;
+            entries.push(entry);
+        }
+        entries.sort_by_key(|(frame, _)| *frame);
+
+        Ok(ScriptedKeypad {
+            script: entries.into(),
+            pressed: [false; constants::NUM_KEYS],
+            events: VecDeque::new(),
+        })
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let script = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read key script {}: {}", path, err))?;
+        Self::new(&script)
+    }
+
+    fn parse_line(line: &str) -> Option<(u64, KeyEvent)> {
+        let mut parts = line.split_whitespace();
+        let frame = parts.next()?.parse::<u64>().ok()?;
+        let action = parts.next()?;
+        let key = u8::from_str_radix(parts.next()?, 16).ok()?;
+        if key as usize >= constants::NUM_KEYS || parts.next().is_some() {
+            return None;
+        }
+        match action {
+            "down" => Some((frame, KeyEvent::Down(key))),
+            "up" => Some((frame, KeyEvent::Up(key))),
+            _ => None,
+        }
+    }
+
+    // Applies every scripted event scheduled up to the given frame
+    fn advance_to(&mut self, frame: u64) {
+        while let Some(&(event_frame, event)) = self.script.front() {
+            if event_frame > frame {
+                break;
+            }
+            match event {
+                KeyEvent::Down(key) => self.pressed[key as usize] = true,
+                KeyEvent::Up(key) => self.pressed[key as usize] = false,
+            }
+            self.events.push_back(event);
+            self.script.pop_front();
+        }
+    }
+}
+
+impl Keypad for ScriptedKeypad {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.pressed.get(key as usize).copied().unwrap_or_default()
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+}
+
+// Runs a ROM without video or audio, on an emulated clock
+pub struct Headless {
+    chip8: chip8::Chip8,
+    keypad: ScriptedKeypad,
+}
+
+impl Headless {
+    pub fn new(
+        rom_path: &str,
+        cycles_per_frame: u32,
+        stack_depth: usize,
+        quirks: Quirks,
+        keypad: ScriptedKeypad,
+        debug: bool,
+    ) -> Result<Self, Chip8Error> {
+        let chip8 = chip8::Chip8::new(rom_path, cycles_per_frame, stack_depth, quirks, debug)?;
+
+        Ok(Headless { chip8, keypad })
+    }
+
+    // Boots into the snapshot stored in the given file
+    pub fn load_state_file(&mut self, state_path: &str) -> Result<(), Chip8Error> {
+        let state = state::read_state_file(state_path)?;
+        self.chip8.load_state(&state)
+    }
+
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.chip8.set_rng(rng);
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.chip8.set_tracer(tracer);
+    }
+
+    pub fn run(&mut self, length: RunLength) -> Result<(), Chip8Error> {
+        match length {
+            RunLength::Frames(frames) => {
+                for frame in 0..frames {
+                    if self.chip8.has_exited() {
+                        break;
+                    }
+                    self.keypad.advance_to(frame);
+                    self.chip8.run_frame(&mut self.keypad)?;
+                }
+            }
+            RunLength::Cycles(cycles) => {
+                let cycles_per_frame = self.chip8.get_cycles_per_frame() as u64;
+                for cycle in 0..cycles {
+                    if self.chip8.has_exited() {
+                        break;
+                    }
+                    // Frames start with their timer tick, as in `Chip8::run_frame`
+                    if cycle % cycles_per_frame == 0 {
+                        self.keypad.advance_to(cycle / cycles_per_frame);
+                        self.chip8.tick_timers();
+                    }
+                    self.chip8.step(&mut self.keypad)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Final machine state: screen, registers and memory
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("Screen:\n");
+        out.push_str(&screen_text(&self.chip8));
+
+        out.push_str(&format!("Registers: {:?}\n", self.chip8.get_registers()));
+        out.push_str(&format!(
+            "Index Register: 0x{:03X}\n",
+            self.chip8.get_index_register()
+        ));
+        out.push_str(&format!(
+            "Program Counter: 0x{:03X}\n",
+            self.chip8.get_program_counter()
+        ));
+
+        out.push_str(&format!("Stack: {:03X?}\n", self.chip8.get_stack()));
+
+        out.push_str("Memory:\n");
+        for (line, bytes) in self.chip8.get_memory().chunks(16).enumerate() {
+            out.push_str(&format!("{:03X}:", line * 16));
+            bytes
+                .iter()
+                .for_each(|byte| out.push_str(&format!(" {:02X}", byte)));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// Screen as text, one character per pixel, indexed by its XO-CHIP plane bits
+pub fn screen_text(chip8: &chip8::Chip8) -> String {
+    const PIXELS: [char; 4] = ['.', '#', '+', '@'];
+    let mut out = String::new();
+    for row in chip8.get_screen().chunks(chip8.get_screen_width()) {
+        row.iter()
+            .for_each(|&pixel| out.push(PIXELS[pixel as usize & 0x03]));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_script_lines() {
+        assert_eq!(
+            ScriptedKeypad::parse_line("120 down A"),
+            Some((120, KeyEvent::Down(0xA)))
+        );
+        assert_eq!(
+            ScriptedKeypad::parse_line("7   up   f"),
+            Some((7, KeyEvent::Up(0xF)))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_script_lines() {
+        for line in [
+            "",
+            "120",
+            "120 down",
+            "x down A",
+            "120 press A",
+            "120 down 10",
+            "120 down G",
+            "120 down A extra",
+            "-1 down A",
+        ] {
+            assert_eq!(ScriptedKeypad::parse_line(line), None, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn script_reports_line_numbers_and_skips_comments() {
+        assert!(ScriptedKeypad::new("# comment\n\n10 down 1\n").is_ok());
+        let err = ScriptedKeypad::new("10 down 1\n20 sideways 1\n")
+            .err()
+            .unwrap();
+        assert!(err.contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn script_events_arrive_on_their_frame() {
+        let mut keypad = ScriptedKeypad::new("5 up 3\n2 down 3\n").unwrap();
+        keypad.advance_to(1);
+        assert_eq!(keypad.next_event(), None);
+        keypad.advance_to(2);
+        assert_eq!(keypad.next_event(), Some(KeyEvent::Down(3)));
+        assert!(keypad.is_pressed(3));
+        keypad.advance_to(5);
+        assert_eq!(keypad.next_event(), Some(KeyEvent::Up(3)));
+        assert!(!keypad.is_pressed(3));
+    }
+}
@@ -0,0 +1,15 @@
+// Key events reported by a keypad, using CHIP-8 key codes (0x0 - 0xF)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    Down(u8),
+    Up(u8),
+}
+
+// Input source queried by the CPU, independent of any frontend
+pub trait Keypad {
+    // Whether the given key is currently held down
+    fn is_pressed(&self, key: u8) -> bool;
+
+    // Next pending key event, if any
+    fn next_event(&mut self) -> Option<KeyEvent>;
+}
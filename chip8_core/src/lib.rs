@@ -0,0 +1,34 @@
+// CHIP-8, SUPER-CHIP and XO-CHIP emulator core, independent of any frontend: the CPU with
+// its memory, display buffer and timers, the instruction decoder, save states, movies,
+// execution traces, a headless runner and the assembler and disassembler
+// Without the default `std` feature only the CPU, decoder and quirks are built, as no_std
+// code that never allocates, and the host boots an `empty` machine in place with
+// `load_rom`, seeding CXNN, then calls `run_frame` 60 times per second
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+pub mod asm;
+pub mod chip8;
+pub mod constants;
+#[cfg(feature = "std")]
+pub mod disasm;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod headless;
+pub mod keypad;
+#[cfg(feature = "std")]
+pub mod movie;
+pub mod opcode;
+pub mod quirks;
+#[cfg(feature = "std")]
+pub mod rewind;
+pub mod rng;
+#[cfg(feature = "std")]
+pub mod state;
+#[cfg(feature = "std")]
+pub mod trace;
+
+pub use chip8::Chip8;
+pub use error::Chip8Error;
+pub use keypad::{KeyEvent, Keypad};
+pub use quirks::Quirks;
@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::fs;
+
+use crate::constants;
+use crate::error::Chip8Error;
+use crate::keypad::{KeyEvent, Keypad};
+use crate::quirks::Quirks;
+use crate::state::{StateReader, StateWriter};
+
+// Movie file header
+const MOVIE_MAGIC: &[u8; 4] = b"C8MV";
+// Bumped whenever the layout of a movie changes
+const MOVIE_VERSION: u8 = 4;
+
+// Recorded play session: the keys held during every emulated frame, along with
+// everything needed to replay it bit-exactly
+pub struct Movie {
+    // Hash of the ROM the movie was recorded with
+    pub rom_hash: u64,
+    pub quirks: Quirks,
+    pub stack_depth: usize,
+    // Instructions executed per frame
+    pub cycles_per_frame: u32,
+    // CXNN uses the COSMAC VIP random number routine
+    pub vip_rng: bool,
+    // Random number generator seed at the first frame
+    pub seed: u64,
+    // Pressed keys of each frame, one bit per key
+    pub frames: Vec<u16>,
+}
+
+impl Movie {
+    pub fn new(
+        rom_hash: u64,
+        quirks: Quirks,
+        stack_depth: usize,
+        cycles_per_frame: u32,
+        vip_rng: bool,
+        seed: u64,
+    ) -> Self {
+        Movie {
+            rom_hash,
+            quirks,
+            stack_depth,
+            cycles_per_frame,
+            vip_rng,
+            seed,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn read(path: &str) -> Result<Self, Chip8Error> {
+        let data =
+            fs::read(path).map_err(|err| Chip8Error::InvalidMovie(format!("{}: {}", path, err)))?;
+        let mut reader =
+            StateReader::new(&data, MOVIE_MAGIC, MOVIE_VERSION, Chip8Error::InvalidMovie)?;
+
+        let rom_hash = reader.read_u64()?;
+        let quirks = Quirks::from_bits(reader.read_u8()?);
+        let stack_depth = reader.read_u16()? as usize;
+        let cycles_per_frame = reader.read_u32()?;
+        let vip_rng = reader.read_bool()?;
+        let seed = reader.read_u64()?;
+        let frame_count = reader.read_u32()?;
+        let frames = (0..frame_count)
+            .map(|_| reader.read_u16())
+            .collect::<Result<Vec<u16>, Chip8Error>>()?;
+        reader.finish()?;
+
+        Ok(Movie {
+            rom_hash,
+            quirks,
+            stack_depth,
+            cycles_per_frame,
+            vip_rng,
+            seed,
+            frames,
+        })
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), Chip8Error> {
+        let stack_depth = u16::try_from(self.stack_depth)
+            .map_err(|_| Chip8Error::InvalidMovie("stack depth out of range".to_string()))?;
+        let mut writer = StateWriter::new(MOVIE_MAGIC, MOVIE_VERSION);
+        writer.write_u64(self.rom_hash);
+        writer.write_u8(self.quirks.to_bits());
+        writer.write_u16(stack_depth);
+        writer.write_u32(self.cycles_per_frame);
+        writer.write_bool(self.vip_rng);
+        writer.write_u64(self.seed);
+        writer.write_u32(self.frames.len() as u32);
+        self.frames.iter().for_each(|&keys| writer.write_u16(keys));
+
+        fs::write(path, writer.finish())
+            .map_err(|err| Chip8Error::InvalidMovie(format!("{}: {}", path, err)))
+    }
+
+    // Refuses playback with another ROM or settings than the ones recorded
+    pub fn check_matches(
+        &self,
+        rom_hash: u64,
+        quirks: Quirks,
+        stack_depth: usize,
+        cycles_per_frame: u32,
+        vip_rng: bool,
+    ) -> Result<(), Chip8Error> {
+        let mismatch = if self.rom_hash != rom_hash {
+            Some("recorded with another ROM")
+        } else if self.quirks != quirks {
+            Some("recorded with other quirks")
+        } else if self.stack_depth != stack_depth {
+            Some("recorded with another stack depth")
+        } else if self.cycles_per_frame != cycles_per_frame {
+            Some("recorded with another emulation speed")
+        } else if self.vip_rng != vip_rng {
+            Some("recorded with another random number generator")
+        } else {
+            None
+        };
+        match mismatch {
+            Some(reason) => Err(Chip8Error::InvalidMovie(reason.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+// 64-bit FNV-1a hash identifying a ROM
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+// Keypad fed with the keys of one frame at a time, reporting the changes as events
+#[derive(Default)]
+pub struct MovieKeypad {
+    keys: u16,
+    events: VecDeque<KeyEvent>,
+}
+
+impl MovieKeypad {
+    pub fn new() -> Self {
+        MovieKeypad {
+            keys: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn set_keys(&mut self, keys: u16) {
+        for key in 0..constants::NUM_KEYS as u8 {
+            let mask = 1 << key;
+            match (self.keys & mask > 0, keys & mask > 0) {
+                (false, true) => self.events.push_back(KeyEvent::Down(key)),
+                (true, false) => self.events.push_back(KeyEvent::Up(key)),
+                _ => {}
+            }
+        }
+        self.keys = keys;
+    }
+}
+
+impl Keypad for MovieKeypad {
+    fn is_pressed(&self, key: u8) -> bool {
+        (key as usize) < constants::NUM_KEYS && self.keys & (1 << key) > 0
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(test: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("chip8_movie_{}_{}", test, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn movie() -> Movie {
+        let mut movie = Movie::new(rom_hash(b"ROM"), Quirks::schip(), 64, 15, true, 42);
+        movie.frames = vec![0x0000, 0x0021, 0x8000, 0xFFFF];
+        movie
+    }
+
+    // Reads back the given bytes as a movie file
+    fn read_bytes(test: &str, data: &[u8]) -> Result<Movie, Chip8Error> {
+        let path = path(test);
+        fs::write(&path, data).unwrap();
+        let movie = Movie::read(&path);
+        fs::remove_file(&path).unwrap();
+        movie
+    }
+
+    fn written(test: &str, movie: &Movie) -> Vec<u8> {
+        let path = path(test);
+        movie.write(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        data
+    }
+
+    fn invalid(reason: &str) -> Result<(), Chip8Error> {
+        Err(Chip8Error::InvalidMovie(reason.to_string()))
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let data = written("round_trip", &movie());
+        let read = read_bytes("round_trip", &data).unwrap();
+        assert_eq!(read.rom_hash, rom_hash(b"ROM"));
+        assert_eq!(read.quirks, Quirks::schip());
+        assert_eq!(read.stack_depth, 64);
+        assert_eq!(read.cycles_per_frame, 15);
+        assert!(read.vip_rng);
+        assert_eq!(read.seed, 42);
+        assert_eq!(read.frames, movie().frames);
+    }
+
+    #[test]
+    fn stores_stack_depths_above_a_byte() {
+        let mut deep = movie();
+        deep.stack_depth = 300;
+        let data = written("deep", &deep);
+        assert_eq!(read_bytes("deep", &data).unwrap().stack_depth, 300);
+
+        deep.stack_depth = 70_000;
+        let path = path("too_deep");
+        assert_eq!(deep.write(&path), invalid("stack depth out of range"));
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let mut data = written("header", &movie());
+        data[0] = b'X';
+        assert_eq!(
+            read_bytes("magic", &data).map(|_| ()),
+            invalid("unknown file header")
+        );
+        data[0] = MOVIE_MAGIC[0];
+        data[4] = MOVIE_VERSION - 1;
+        assert_eq!(
+            read_bytes("version", &data).map(|_| ()),
+            invalid(&format!(
+                "unsupported version {}, expected {}",
+                MOVIE_VERSION - 1,
+                MOVIE_VERSION
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_a_truncated_input_log() {
+        let data = written("truncated", &movie());
+        assert_eq!(
+            read_bytes("truncated", &data[..data.len() - 1]).map(|_| ()),
+            invalid("truncated file")
+        );
+        let mut longer = data.clone();
+        longer.push(0);
+        assert_eq!(
+            read_bytes("longer", &longer).map(|_| ()),
+            invalid("unexpected data at the end")
+        );
+    }
+
+    #[test]
+    fn checks_the_recorded_settings() {
+        let movie = movie();
+        let hash = rom_hash(b"ROM");
+        assert_eq!(
+            movie.check_matches(hash, Quirks::schip(), 64, 15, true),
+            Ok(())
+        );
+        assert_eq!(
+            movie.check_matches(rom_hash(b"ROM2"), Quirks::schip(), 64, 15, true),
+            invalid("recorded with another ROM")
+        );
+        assert_eq!(
+            movie.check_matches(hash, Quirks::vip(), 64, 15, true),
+            invalid("recorded with other quirks")
+        );
+        assert_eq!(
+            movie.check_matches(hash, Quirks::schip(), 16, 15, true),
+            invalid("recorded with another stack depth")
+        );
+        assert_eq!(
+            movie.check_matches(hash, Quirks::schip(), 64, 8, true),
+            invalid("recorded with another emulation speed")
+        );
+        assert_eq!(
+            movie.check_matches(hash, Quirks::schip(), 64, 15, false),
+            invalid("recorded with another random number generator")
+        );
+    }
+
+    #[test]
+    fn hashes_roms_with_fnv1a() {
+        assert_eq!(rom_hash(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(rom_hash(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_ne!(rom_hash(&[0x12, 0x00]), rom_hash(&[0x00, 0x12]));
+    }
+
+    #[test]
+    fn movie_keypad_reports_changes_as_events() {
+        let mut keypad = MovieKeypad::new();
+        keypad.set_keys(0b101);
+        keypad.set_keys(0b110);
+        assert!(keypad.is_pressed(1) && keypad.is_pressed(2) && !keypad.is_pressed(0));
+        assert!(!keypad.is_pressed(16));
+        let events: Vec<KeyEvent> = core::iter::from_fn(|| keypad.next_event()).collect();
+        assert_eq!(
+            events,
+            [
+                KeyEvent::Down(0),
+                KeyEvent::Down(2),
+                KeyEvent::Up(0),
+                KeyEvent::Down(1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use core::fmt;
+
+// Decoded CHIP-8, SUPER-CHIP and XO-CHIP instruction
+// Registers are indexes 0x0 - 0xF, addresses are 12 bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    // 0NNN, machine code routine
+    Sys(u16),
+    // 00E0
+    Cls,
+    // 00EE
+    Ret,
+    // 00CN, SUPER-CHIP
+    ScrollDown(u8),
+    // 00DN, XO-CHIP
+    ScrollUp(u8),
+    // 00FB, SUPER-CHIP
+    ScrollRight,
+    // 00FC, SUPER-CHIP
+    ScrollLeft,
+    // 00FD, SUPER-CHIP
+    Exit,
+    // 00FE, SUPER-CHIP
+    Lores,
+    // 00FF, SUPER-CHIP
+    Hires,
+    // 1NNN
+    Jp(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SeByte { x: u8, byte: u8 },
+    // 4XNN
+    SneByte { x: u8, byte: u8 },
+    // 5XY0
+    SeReg { x: u8, y: u8 },
+    // 5XY2, XO-CHIP
+    SaveRange { x: u8, y: u8 },
+    // 5XY3, XO-CHIP
+    LoadRange { x: u8, y: u8 },
+    // 6XNN
+    LdByte { x: u8, byte: u8 },
+    // 7XNN
+    AddByte { x: u8, byte: u8 },
+    // 8XY0
+    LdReg { x: u8, y: u8 },
+    // 8XY1
+    Or { x: u8, y: u8 },
+    // 8XY2
+    And { x: u8, y: u8 },
+    // 8XY3
+    Xor { x: u8, y: u8 },
+    // 8XY4
+    AddReg { x: u8, y: u8 },
+    // 8XY5
+    Sub { x: u8, y: u8 },
+    // 8XY6
+    Shr { x: u8, y: u8 },
+    // 8XY7
+    Subn { x: u8, y: u8 },
+    // 8XYE
+    Shl { x: u8, y: u8 },
+    // 9XY0
+    SneReg { x: u8, y: u8 },
+    // ANNN
+    LdI(u16),
+    // BNNN, BXNN with the jump quirk
+    JpV0(u16),
+    // CXNN
+    Rnd { x: u8, byte: u8 },
+    // DXYN
+    Drw { x: u8, y: u8, n: u8 },
+    // EX9E
+    Skp(u8),
+    // EXA1
+    Sknp(u8),
+    // F000 NNNN, XO-CHIP, the address is the next word
+    LdILong,
+    // FX01, XO-CHIP, X is the plane mask
+    Plane(u8),
+    // F002, XO-CHIP
+    Audio,
+    // FX07
+    LdVxDt(u8),
+    // FX0A
+    LdKey(u8),
+    // FX15
+    LdDt(u8),
+    // FX18
+    LdSt(u8),
+    // FX1E
+    AddI(u8),
+    // FX29
+    LdF(u8),
+    // FX30, SUPER-CHIP
+    LdHf(u8),
+    // FX33
+    Bcd(u8),
+    // FX3A, XO-CHIP
+    Pitch(u8),
+    // FX55
+    Store(u8),
+    // FX65
+    Load(u8),
+    // FX75, SUPER-CHIP
+    SaveFlags(u8),
+    // FX85, SUPER-CHIP
+    LoadFlags(u8),
+}
+
+// Group of related instructions, for trace filters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpcodeClass {
+    // Jumps, calls, returns and exit
+    Flow,
+    // Skips on register values
+    Skip,
+    // Register loads and arithmetic
+    Alu,
+    // Index register loads and additions
+    Index,
+    // Loads and stores through I, and the RPL flags
+    Memory,
+    // Screen clearing, drawing, scrolling, resolution and planes
+    Display,
+    // Delay and sound timers
+    Timer,
+    // Key skips and waits
+    Input,
+    // CXNN
+    Random,
+    // XO-CHIP audio pattern and pitch
+    Audio,
+}
+
+impl OpcodeClass {
+    pub const NAMES: [&'static str; 10] = [
+        "flow", "skip", "alu", "index", "memory", "display", "timer", "input", "random", "audio",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flow" => Some(OpcodeClass::Flow),
+            "skip" => Some(OpcodeClass::Skip),
+            "alu" => Some(OpcodeClass::Alu),
+            "index" => Some(OpcodeClass::Index),
+            "memory" => Some(OpcodeClass::Memory),
+            "display" => Some(OpcodeClass::Display),
+            "timer" => Some(OpcodeClass::Timer),
+            "input" => Some(OpcodeClass::Input),
+            "random" => Some(OpcodeClass::Random),
+            "audio" => Some(OpcodeClass::Audio),
+            _ => None,
+        }
+    }
+}
+
+// Word that is not an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Unknown(pub u16);
+
+impl Opcode {
+    // Size in memory [B]
+    pub fn size(&self) -> usize {
+        match self {
+            Opcode::LdILong => 4,
+            _ => 2,
+        }
+    }
+
+    pub fn class(&self) -> OpcodeClass {
+        match self {
+            Opcode::Sys(_)
+            | Opcode::Ret
+            | Opcode::Exit
+            | Opcode::Jp(_)
+            | Opcode::Call(_)
+            | Opcode::JpV0(_) => OpcodeClass::Flow,
+            Opcode::SeByte { .. }
+            | Opcode::SneByte { .. }
+            | Opcode::SeReg { .. }
+            | Opcode::SneReg { .. } => OpcodeClass::Skip,
+            Opcode::LdByte { .. }
+            | Opcode::AddByte { .. }
+            | Opcode::LdReg { .. }
+            | Opcode::Or { .. }
+            | Opcode::And { .. }
+            | Opcode::Xor { .. }
+            | Opcode::AddReg { .. }
+            | Opcode::Sub { .. }
+            | Opcode::Shr { .. }
+            | Opcode::Subn { .. }
+            | Opcode::Shl { .. } => OpcodeClass::Alu,
+            Opcode::LdI(_)
+            | Opcode::LdILong
+            | Opcode::AddI(_)
+            | Opcode::LdF(_)
+            | Opcode::LdHf(_) => OpcodeClass::Index,
+            Opcode::SaveRange { .. }
+            | Opcode::LoadRange { .. }
+            | Opcode::Bcd(_)
+            | Opcode::Store(_)
+            | Opcode::Load(_)
+            | Opcode::SaveFlags(_)
+            | Opcode::LoadFlags(_) => OpcodeClass::Memory,
+            Opcode::Cls
+            | Opcode::ScrollDown(_)
+            | Opcode::ScrollUp(_)
+            | Opcode::ScrollRight
+            | Opcode::ScrollLeft
+            | Opcode::Lores
+            | Opcode::Hires
+            | Opcode::Drw { .. }
+            | Opcode::Plane(_) => OpcodeClass::Display,
+            Opcode::LdVxDt(_) | Opcode::LdDt(_) | Opcode::LdSt(_) => OpcodeClass::Timer,
+            Opcode::Skp(_) | Opcode::Sknp(_) | Opcode::LdKey(_) => OpcodeClass::Input,
+            Opcode::Rnd { .. } => OpcodeClass::Random,
+            Opcode::Audio | Opcode::Pitch(_) => OpcodeClass::Audio,
+        }
+    }
+}
+
+pub fn decode(word: u16) -> Result<Opcode, Unknown> {
+    let x = ((word & 0x0F00) >> 8) as u8;
+    let y = ((word & 0x00F0) >> 4) as u8;
+    let n = (word & 0x000F) as u8;
+    let nn = (word & 0x00FF) as u8;
+    let nnn = word & 0x0FFF;
+
+    let opcode = match word & 0xF000 {
+        0x0000 => match nnn {
+            0x0E0 => Opcode::Cls,
+            0x0EE => Opcode::Ret,
+            0x0C0..=0x0CF => Opcode::ScrollDown(n),
+            0x0D0..=0x0DF => Opcode::ScrollUp(n),
+            0x0FB => Opcode::ScrollRight,
+            0x0FC => Opcode::ScrollLeft,
+            0x0FD => Opcode::Exit,
+            0x0FE => Opcode::Lores,
+            0x0FF => Opcode::Hires,
+            _ => Opcode::Sys(nnn),
+        },
+        0x1000 => Opcode::Jp(nnn),
+        0x2000 => Opcode::Call(nnn),
+        0x3000 => Opcode::SeByte { x, byte: nn },
+        0x4000 => Opcode::SneByte { x, byte: nn },
+        0x5000 => match n {
+            0x0 => Opcode::SeReg { x, y },
+            0x2 => Opcode::SaveRange { x, y },
+            0x3 => Opcode::LoadRange { x, y },
+            _ => return Err(Unknown(word)),
+        },
+        0x6000 => Opcode::LdByte { x, byte: nn },
+        0x7000 => Opcode::AddByte { x, byte: nn },
+        0x8000 => match n {
+            0x0 => Opcode::LdReg { x, y },
+            0x1 => Opcode::Or { x, y },
+            0x2 => Opcode::And { x, y },
+            0x3 => Opcode::Xor { x, y },
+            0x4 => Opcode::AddReg { x, y },
+            0x5 => Opcode::Sub { x, y },
+            0x6 => Opcode::Shr { x, y },
+            0x7 => Opcode::Subn { x, y },
+            0xE => Opcode::Shl { x, y },
+            _ => return Err(Unknown(word)),
+        },
+        0x9000 => match n {
+            0x0 => Opcode::SneReg { x, y },
+            _ => return Err(Unknown(word)),
+        },
+        0xA000 => Opcode::LdI(nnn),
+        0xB000 => Opcode::JpV0(nnn),
+        0xC000 => Opcode::Rnd { x, byte: nn },
+        0xD000 => Opcode::Drw { x, y, n },
+        0xE000 => match nn {
+            0x9E => Opcode::Skp(x),
+            0xA1 => Opcode::Sknp(x),
+            _ => return Err(Unknown(word)),
+        },
+        _ => match nn {
+            0x00 if x == 0 => Opcode::LdILong,
+            0x01 => Opcode::Plane(x),
+            0x02 if x == 0 => Opcode::Audio,
+            0x07 => Opcode::LdVxDt(x),
+            0x0A => Opcode::LdKey(x),
+            0x15 => Opcode::LdDt(x),
+            0x18 => Opcode::LdSt(x),
+            0x1E => Opcode::AddI(x),
+            0x29 => Opcode::LdF(x),
+            0x30 => Opcode::LdHf(x),
+            0x33 => Opcode::Bcd(x),
+            0x3A => Opcode::Pitch(x),
+            0x55 => Opcode::Store(x),
+            0x65 => Opcode::Load(x),
+            0x75 => Opcode::SaveFlags(x),
+            0x85 => Opcode::LoadFlags(x),
+            _ => return Err(Unknown(word)),
+        },
+    };
+    Ok(opcode)
+}
+
+// Inverse of `decode`, F000 NNNN is encoded without its address word
+pub fn encode(opcode: Opcode) -> u16 {
+    let xy = |base: u16, x: u8, y: u8| base | (x as u16) << 8 | (y as u16) << 4;
+    let xnn = |base: u16, x: u8, nn: u8| base | (x as u16) << 8 | nn as u16;
+    let fx = |nn: u8, x: u8| xnn(0xF000, x, nn);
+
+    match opcode {
+        Opcode::Sys(addr) => addr & 0x0FFF,
+        Opcode::Cls => 0x00E0,
+        Opcode::Ret => 0x00EE,
+        Opcode::ScrollDown(n) => 0x00C0 | (n & 0x0F) as u16,
+        Opcode::ScrollUp(n) => 0x00D0 | (n & 0x0F) as u16,
+        Opcode::ScrollRight => 0x00FB,
+        Opcode::ScrollLeft => 0x00FC,
+        Opcode::Exit => 0x00FD,
+        Opcode::Lores => 0x00FE,
+        Opcode::Hires => 0x00FF,
+        Opcode::Jp(addr) => 0x1000 | addr & 0x0FFF,
+        Opcode::Call(addr) => 0x2000 | addr & 0x0FFF,
+        Opcode::SeByte { x, byte } => xnn(0x3000, x, byte),
+        Opcode::SneByte { x, byte } => xnn(0x4000, x, byte),
+        Opcode::SeReg { x, y } => xy(0x5000, x, y),
+        Opcode::SaveRange { x, y } => xy(0x5002, x, y),
+        Opcode::LoadRange { x, y } => xy(0x5003, x, y),
+        Opcode::LdByte { x, byte } => xnn(0x6000, x, byte),
+        Opcode::AddByte { x, byte } => xnn(0x7000, x, byte),
+        Opcode::LdReg { x, y } => xy(0x8000, x, y),
+        Opcode::Or { x, y } => xy(0x8001, x, y),
+        Opcode::And { x, y } => xy(0x8002, x, y),
+        Opcode::Xor { x, y } => xy(0x8003, x, y),
+        Opcode::AddReg { x, y } => xy(0x8004, x, y),
+        Opcode::Sub { x, y } => xy(0x8005, x, y),
+        Opcode::Shr { x, y } => xy(0x8006, x, y),
+        Opcode::Subn { x, y } => xy(0x8007, x, y),
+        Opcode::Shl { x, y } => xy(0x800E, x, y),
+        Opcode::SneReg { x, y } => xy(0x9000, x, y),
+        Opcode::LdI(addr) => 0xA000 | addr & 0x0FFF,
+        Opcode::JpV0(addr) => 0xB000 | addr & 0x0FFF,
+        Opcode::Rnd { x, byte } => xnn(0xC000, x, byte),
+        Opcode::Drw { x, y, n } => xy(0xD000, x, y) | (n & 0x0F) as u16,
+        Opcode::Skp(x) => xnn(0xE000, x, 0x9E),
+        Opcode::Sknp(x) => xnn(0xE000, x, 0xA1),
+        Opcode::LdILong => 0xF000,
+        Opcode::Plane(x) => fx(0x01, x),
+        Opcode::Audio => 0xF002,
+        Opcode::LdVxDt(x) => fx(0x07, x),
+        Opcode::LdKey(x) => fx(0x0A, x),
+        Opcode::LdDt(x) => fx(0x15, x),
+        Opcode::LdSt(x) => fx(0x18, x),
+        Opcode::AddI(x) => fx(0x1E, x),
+        Opcode::LdF(x) => fx(0x29, x),
+        Opcode::LdHf(x) => fx(0x30, x),
+        Opcode::Bcd(x) => fx(0x33, x),
+        Opcode::Pitch(x) => fx(0x3A, x),
+        Opcode::Store(x) => fx(0x55, x),
+        Opcode::Load(x) => fx(0x65, x),
+        Opcode::SaveFlags(x) => fx(0x75, x),
+        Opcode::LoadFlags(x) => fx(0x85, x),
+    }
+}
+
+// Cowgod's mnemonics, extended with the usual SUPER-CHIP and XO-CHIP ones
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Opcode::Sys(addr) => write!(f, "SYS 0x{:03X}", addr),
+            Opcode::Cls => write!(f, "CLS"),
+            Opcode::Ret => write!(f, "RET"),
+            Opcode::ScrollDown(n) => write!(f, "SCD {}", n),
+            Opcode::ScrollUp(n) => write!(f, "SCU {}", n),
+            Opcode::ScrollRight => write!(f, "SCR"),
+            Opcode::ScrollLeft => write!(f, "SCL"),
+            Opcode::Exit => write!(f, "EXIT"),
+            Opcode::Lores => write!(f, "LOW"),
+            Opcode::Hires => write!(f, "HIGH"),
+            Opcode::Jp(addr) => write!(f, "JP 0x{:03X}", addr),
+            Opcode::Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            Opcode::SeByte { x, byte } => write!(f, "SE V{:X}, 0x{:02X}", x, byte),
+            Opcode::SneByte { x, byte } => write!(f, "SNE V{:X}, 0x{:02X}", x, byte),
+            Opcode::SeReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Opcode::SaveRange { x, y } => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Opcode::LoadRange { x, y } => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            Opcode::LdByte { x, byte } => write!(f, "LD V{:X}, 0x{:02X}", x, byte),
+            Opcode::AddByte { x, byte } => write!(f, "ADD V{:X}, 0x{:02X}", x, byte),
+            Opcode::LdReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Opcode::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Opcode::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Opcode::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Opcode::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Opcode::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Opcode::Shr { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Opcode::Subn { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Opcode::Shl { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Opcode::SneReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Opcode::LdI(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            Opcode::JpV0(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            Opcode::Rnd { x, byte } => write!(f, "RND V{:X}, 0x{:02X}", x, byte),
+            Opcode::Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Opcode::Skp(x) => write!(f, "SKP V{:X}", x),
+            Opcode::Sknp(x) => write!(f, "SKNP V{:X}", x),
+            Opcode::LdILong => write!(f, "LD I, LONG"),
+            Opcode::Plane(x) => write!(f, "PLANE {}", x),
+            Opcode::Audio => write!(f, "AUDIO"),
+            Opcode::LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            Opcode::LdKey(x) => write!(f, "LD V{:X}, K", x),
+            Opcode::LdDt(x) => write!(f, "LD DT, V{:X}", x),
+            Opcode::LdSt(x) => write!(f, "LD ST, V{:X}", x),
+            Opcode::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Opcode::LdF(x) => write!(f, "LD F, V{:X}", x),
+            Opcode::LdHf(x) => write!(f, "LD HF, V{:X}", x),
+            Opcode::Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Opcode::Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Opcode::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Opcode::Load(x) => write!(f, "LD V{:X}, [I]", x),
+            Opcode::SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            Opcode::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_inverts_decode_for_every_word() {
+        let mut decoded = 0;
+        for word in 0..=u16::MAX {
+            if let Ok(opcode) = decode(word) {
+                assert_eq!(encode(opcode), word, "{:04X} decodes to {:?}", word, opcode);
+                decoded += 1;
+            }
+        }
+        // 11 fully used nibbles, 5XY0/2/3, 8XY0 - 8XY7 and 8XYE, 9XY0, EX9E/A1, 14 FXNN, F000 and
+        // F002
+        assert_eq!(
+            decoded,
+            11 * 0x1000 + 3 * 0x100 + 9 * 0x100 + 0x100 + 2 * 0x10 + 14 * 0x10 + 2
+        );
+    }
+
+    #[test]
+    fn rejects_words_outside_the_instruction_set() {
+        for word in [
+            0x5121, 0x5FF4, 0x5ABF, 0x8128, 0x8ABD, 0x8CDF, 0x9121, 0x9ABF,
+        ] {
+            assert_eq!(decode(word), Err(Unknown(word)), "{:04X}", word);
+        }
+        for nn in (0x00..=0xFF).filter(|nn| ![0x9E, 0xA1].contains(nn)) {
+            let word = 0xE300 | nn;
+            assert_eq!(decode(word), Err(Unknown(word)), "{:04X}", word);
+        }
+        for word in [0xF100, 0xF102, 0xF108, 0xF1A5, 0xF1FF] {
+            assert_eq!(decode(word), Err(Unknown(word)), "{:04X}", word);
+        }
+    }
+
+    #[test]
+    fn decodes_super_chip_instructions() {
+        assert_eq!(decode(0x00C5), Ok(Opcode::ScrollDown(5)));
+        assert_eq!(decode(0x00FB), Ok(Opcode::ScrollRight));
+        assert_eq!(decode(0x00FC), Ok(Opcode::ScrollLeft));
+        assert_eq!(decode(0x00FD), Ok(Opcode::Exit));
+        assert_eq!(decode(0x00FE), Ok(Opcode::Lores));
+        assert_eq!(decode(0x00FF), Ok(Opcode::Hires));
+        assert_eq!(decode(0xF730), Ok(Opcode::LdHf(7)));
+        assert_eq!(decode(0xF775), Ok(Opcode::SaveFlags(7)));
+        assert_eq!(decode(0xF785), Ok(Opcode::LoadFlags(7)));
+        // Neighbours of the 00CN - 00FF block stay machine code routines
+        assert_eq!(decode(0x00BF), Ok(Opcode::Sys(0x0BF)));
+        assert_eq!(decode(0x00FA), Ok(Opcode::Sys(0x0FA)));
+        assert_eq!(decode(0x01FF), Ok(Opcode::Sys(0x1FF)));
+    }
+
+    #[test]
+    fn decodes_xo_chip_instructions() {
+        assert_eq!(decode(0x00D3), Ok(Opcode::ScrollUp(3)));
+        assert_eq!(decode(0x5122), Ok(Opcode::SaveRange { x: 1, y: 2 }));
+        assert_eq!(decode(0x5123), Ok(Opcode::LoadRange { x: 1, y: 2 }));
+        assert_eq!(decode(0xF201), Ok(Opcode::Plane(2)));
+        assert_eq!(decode(0xF43A), Ok(Opcode::Pitch(4)));
+        // F000 NNNN and F002 only exist with X = 0
+        assert_eq!(decode(0xF000), Ok(Opcode::LdILong));
+        assert_eq!(decode(0xF002), Ok(Opcode::Audio));
+        assert_eq!(decode(0xF300), Err(Unknown(0xF300)));
+        assert_eq!(decode(0xF302), Err(Unknown(0xF302)));
+        assert_eq!(Opcode::LdILong.size(), 4);
+        assert_eq!(Opcode::Audio.size(), 2);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::constants;
+
+// Behaviours which differ between CHIP-8 interpreters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub logic_resets_vf: bool,
+    // FX55/FX65 leave I pointing past the last register
+    pub load_store_increments_i: bool,
+    // BNNN jumps to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // DXYN wraps sprites around the screen edges instead of clipping them
+    pub wrap_sprites: bool,
+    // DXYN waits for the next 60 Hz display refresh
+    pub display_wait: bool,
+    // 64K of memory instead of 4K
+    pub memory_64k: bool,
+}
+
+impl Quirks {
+    // Names accepted by `from_preset`
+    pub const PRESETS: [&'static str; 5] = ["vip", "chip48", "schip", "xochip", "cowgod"];
+    // Names accepted by `apply_override`
+    pub const NAMES: [&'static str; 7] = [
+        "shift",
+        "vf-reset",
+        "load-store-i",
+        "jump",
+        "wrap",
+        "display-wait",
+        "memory-64k",
+    ];
+
+    // Original COSMAC VIP interpreter
+    pub fn vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            logic_resets_vf: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            wrap_sprites: false,
+            display_wait: true,
+            memory_64k: false,
+        }
+    }
+
+    // CHIP-48 on the HP-48 calculators
+    pub fn chip48() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            logic_resets_vf: false,
+            load_store_increments_i: true,
+            jump_uses_vx: true,
+            wrap_sprites: false,
+            display_wait: false,
+            memory_64k: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1
+    pub fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            logic_resets_vf: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            wrap_sprites: false,
+            display_wait: false,
+            memory_64k: false,
+        }
+    }
+
+    // Cowgod's Chip-8 Technical Reference, the behaviour of the emulator before quirks
+    // became configurable
+    pub const fn cowgod() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            logic_resets_vf: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            wrap_sprites: true,
+            display_wait: false,
+            memory_64k: false,
+        }
+    }
+
+    // XO-CHIP, as implemented by Octo
+    pub fn xochip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            logic_resets_vf: false,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            wrap_sprites: true,
+            display_wait: false,
+            memory_64k: true,
+        }
+    }
+
+    // Addressable memory [B]
+    pub fn memory_size(&self) -> usize {
+        if self.memory_64k {
+            constants::XO_CHIP_MEMORY_IN_B
+        } else {
+            constants::MEMORY_IN_B
+        }
+    }
+
+    // Packs the quirks into bit flags, in declaration order
+    pub fn to_bits(self) -> u8 {
+        [
+            self.shift_uses_vy,
+            self.logic_resets_vf,
+            self.load_store_increments_i,
+            self.jump_uses_vx,
+            self.wrap_sprites,
+            self.display_wait,
+            self.memory_64k,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (bit, &quirk)| bits | (quirk as u8) << bit)
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        let bit = |index: u8| bits & (1 << index) > 0;
+        Quirks {
+            shift_uses_vy: bit(0),
+            logic_resets_vf: bit(1),
+            load_store_increments_i: bit(2),
+            jump_uses_vx: bit(3),
+            wrap_sprites: bit(4),
+            display_wait: bit(5),
+            memory_64k: bit(6),
+        }
+    }
+
+    pub fn from_preset(name: &str) -> Option<Self> {
+        match name {
+            "vip" => Some(Self::vip()),
+            "chip48" => Some(Self::chip48()),
+            "schip" => Some(Self::schip()),
+            "xochip" => Some(Self::xochip()),
+            "cowgod" => Some(Self::cowgod()),
+            _ => None,
+        }
+    }
+
+    // Overrides a single quirk, given as `<name>=<on|off>`
+    #[cfg(feature = "std")]
+    pub fn apply_override(&mut self, quirk: &str) -> Result<(), String> {
+        let invalid = || {
+            format!(
+                "Invalid quirk `{}`, expected `<name>=<on|off>` with name one of: {}",
+                quirk,
+                Self::NAMES.join(", ")
+            )
+        };
+        let (name, value) = quirk.split_once('=').ok_or_else(invalid)?;
+        let value = match value {
+            "on" | "true" | "1" => true,
+            "off" | "false" | "0" => false,
+            _ => return Err(invalid()),
+        };
+
+        match name {
+            "shift" => self.shift_uses_vy = value,
+            "vf-reset" => self.logic_resets_vf = value,
+            "load-store-i" => self.load_store_increments_i = value,
+            "jump" => self.jump_uses_vx = value,
+            "wrap" => self.wrap_sprites = value,
+            "display-wait" => self.display_wait = value,
+            "memory-64k" => self.memory_64k = value,
+            _ => return Err(invalid()),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::cowgod()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keeps_the_cowgod_behaviour() {
+        assert_eq!(Quirks::default(), Quirks::cowgod());
+    }
+
+    #[test]
+    fn every_preset_round_trips_through_bits() {
+        for preset in Quirks::PRESETS {
+            let quirks = Quirks::from_preset(preset).unwrap();
+            assert_eq!(Quirks::from_bits(quirks.to_bits()), quirks, "{}", preset);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn overrides_every_named_quirk() {
+        // Each name turns on its own quirk, in declaration order
+        for (bit, name) in Quirks::NAMES.iter().enumerate() {
+            let mut quirks = Quirks::from_bits(0);
+            quirks.apply_override(&format!("{}=on", name)).unwrap();
+            assert_eq!(quirks.to_bits(), 1 << bit, "{}", name);
+        }
+        let mut quirks = Quirks::default();
+        quirks.apply_override("load-store-i=on").unwrap();
+        assert!(quirks.load_store_increments_i);
+        assert!(quirks.apply_override("memory=on").is_err());
+        assert!(quirks.apply_override("shift").is_err());
+    }
+}
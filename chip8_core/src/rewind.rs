@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+// Difference between a save state and the one recorded before it
+enum Delta {
+    // Run length encoded XOR of the two states
+    Xor(Vec<u8>),
+    // Previous state, kept whole when the states differ in size
+    Full(Vec<u8>),
+}
+
+// Bounded history of save states, stored as deltas from the newest state backwards
+pub struct RewindBuffer {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Records a state, dropping the oldest one once the buffer is full
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            let delta = if previous.len() == state.len() {
+                Delta::Xor(Self::encode(&previous, &state))
+            } else {
+                Delta::Full(previous)
+            };
+            self.deltas.push_back(delta);
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(state);
+    }
+
+    // Removes the newest state and returns the one recorded before it
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        // Without an older state the newest one stays in place
+        let delta = self.deltas.pop_back()?;
+        let newest = self.newest.take()?;
+        let previous = match delta {
+            Delta::Xor(delta) => Self::decode(&newest, &delta),
+            Delta::Full(previous) => previous,
+        };
+        self.newest = Some(previous.clone());
+        Some(previous)
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    // XORs the states and encodes the result as (zero run, literal length, literal bytes)
+    // chunks, both lengths as u16 little endian
+    fn encode(previous: &[u8], state: &[u8]) -> Vec<u8> {
+        let xor: Vec<u8> = previous.iter().zip(state).map(|(a, b)| a ^ b).collect();
+        let mut encoded = Vec::new();
+        let mut pos = 0;
+        while pos < xor.len() {
+            let zeros = xor[pos..]
+                .iter()
+                .take(u16::MAX as usize)
+                .take_while(|&&byte| byte == 0)
+                .count();
+            pos += zeros;
+            let literal = xor[pos..]
+                .iter()
+                .take(u16::MAX as usize)
+                .take_while(|&&byte| byte != 0)
+                .count();
+            encoded.extend_from_slice(&(zeros as u16).to_le_bytes());
+            encoded.extend_from_slice(&(literal as u16).to_le_bytes());
+            encoded.extend_from_slice(&xor[pos..pos + literal]);
+            pos += literal;
+        }
+        encoded
+    }
+
+    // Applies an encoded XOR delta to a state
+    fn decode(state: &[u8], delta: &[u8]) -> Vec<u8> {
+        let mut decoded = state.to_vec();
+        let mut pos = 0;
+        let mut chunks = delta;
+        while chunks.len() >= 4 {
+            let zeros = u16::from_le_bytes([chunks[0], chunks[1]]) as usize;
+            let literal = u16::from_le_bytes([chunks[2], chunks[3]]) as usize;
+            pos += zeros;
+            decoded[pos..pos + literal]
+                .iter_mut()
+                .zip(&chunks[4..4 + literal])
+                .for_each(|(byte, xor)| *byte ^= xor);
+            pos += literal;
+            chunks = &chunks[4 + literal..];
+        }
+        decoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_states_newest_first() {
+        let mut rewind = RewindBuffer::new(8);
+        for value in 0..4u8 {
+            rewind.push(vec![value; 16]);
+        }
+        assert_eq!(rewind.pop(), Some(vec![2; 16]));
+        assert_eq!(rewind.pop(), Some(vec![1; 16]));
+        assert_eq!(rewind.pop(), Some(vec![0; 16]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn keeps_the_oldest_state_when_empty() {
+        let mut rewind = RewindBuffer::new(8);
+        assert_eq!(rewind.pop(), None);
+        rewind.push(vec![7; 4]);
+        assert_eq!(rewind.pop(), None);
+        // A failed pop leaves the state in place to record the next delta against
+        rewind.push(vec![8; 4]);
+        assert_eq!(rewind.pop(), Some(vec![7; 4]));
+    }
+
+    #[test]
+    fn drops_the_oldest_states_past_capacity() {
+        let mut rewind = RewindBuffer::new(2);
+        for value in 0..5u8 {
+            rewind.push(vec![value; 16]);
+        }
+        assert_eq!(rewind.pop(), Some(vec![3; 16]));
+        assert_eq!(rewind.pop(), Some(vec![2; 16]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn restores_states_of_different_sizes() {
+        let mut rewind = RewindBuffer::new(8);
+        let long: Vec<u8> = (0..=255).cycle().take(70_000).collect();
+        rewind.push(vec![1, 2, 3]);
+        rewind.push(long.clone());
+        rewind.push(vec![0; 70_000]);
+        assert_eq!(rewind.pop(), Some(long));
+        assert_eq!(rewind.pop(), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn clear_forgets_every_state() {
+        let mut rewind = RewindBuffer::new(8);
+        rewind.push(vec![1]);
+        rewind.push(vec![2]);
+        rewind.clear();
+        assert_eq!(rewind.pop(), None);
+    }
+}
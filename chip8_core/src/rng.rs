@@ -0,0 +1,160 @@
+use crate::constants;
+#[cfg(feature = "std")]
+use crate::error::Chip8Error;
+
+// Seedable pseudo random number generator for CXNN
+// Its whole state is a single word, so it can be stored in save states and movies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+    // Page 0x100 - 0x1FF of the COSMAC VIP interpreter, switches to the VIP algorithm
+    vip_page: Option<[u8; constants::VIP_PAGE_SIZE]>,
+}
+
+impl Rng {
+    // SplitMix64, fast and statistically sound
+    pub const fn new(seed: u64) -> Self {
+        Rng {
+            state: seed,
+            vip_page: None,
+        }
+    }
+
+    // Routine of the original COSMAC VIP interpreter, which adds a byte of its own code
+    // to the high byte of the R9 counter, the state being R9
+    pub fn vip(seed: u64, vip_page: [u8; constants::VIP_PAGE_SIZE]) -> Self {
+        Rng {
+            state: seed & 0xFFFF,
+            vip_page: Some(vip_page),
+        }
+    }
+
+    // Reads the interpreter page from a 512 byte dump of the VIP interpreter
+    #[cfg(feature = "std")]
+    pub fn read_vip_page(path: &str) -> Result<[u8; constants::VIP_PAGE_SIZE], Chip8Error> {
+        let interpreter = std::fs::read(path)
+            .map_err(|err| Chip8Error::InvalidInterpreter(format!("{}: {}", path, err)))?;
+        if interpreter.len() != constants::PROGRAM_MEMORY_START {
+            return Err(Chip8Error::InvalidInterpreter(format!(
+                "{} bytes, expected {}",
+                interpreter.len(),
+                constants::PROGRAM_MEMORY_START
+            )));
+        }
+        let mut vip_page = [0; constants::VIP_PAGE_SIZE];
+        vip_page.copy_from_slice(&interpreter[constants::VIP_PAGE_SIZE..]);
+        Ok(vip_page)
+    }
+
+    // Restarts the generator from the given seed, keeping its algorithm
+    pub fn reseed(&mut self, seed: u64) {
+        self.state = match self.vip_page {
+            Some(_) => seed & 0xFFFF,
+            None => seed,
+        };
+    }
+
+    pub fn is_vip(&self) -> bool {
+        self.vip_page.is_some()
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        match &self.vip_page {
+            Some(vip_page) => {
+                // INC R9, then R9.1 += M(0x0100 + R9.0)
+                let r9 = (self.state as u16).wrapping_add(1);
+                let random = vip_page[(r9 & 0xFF) as usize].wrapping_add((r9 >> 8) as u8);
+                self.state = ((random as u16) << 8 | r9 & 0xFF) as u64;
+                random
+            }
+            None => (self.next_u64() >> 56) as u8,
+        }
+    }
+
+    // The VIP interrupt routine increments R9 every 60 Hz frame
+    pub fn tick(&mut self) {
+        if self.vip_page.is_some() {
+            self.state = (self.state as u16).wrapping_add(1) as u64;
+        }
+    }
+
+    pub fn get_state(&self) -> u64 {
+        self.state
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Page whose bytes are their own offsets, so each VIP step is easy to follow by hand
+    fn identity_page() -> [u8; constants::VIP_PAGE_SIZE] {
+        let mut page = [0; constants::VIP_PAGE_SIZE];
+        page.iter_mut()
+            .enumerate()
+            .for_each(|(offset, byte)| *byte = offset as u8);
+        page
+    }
+
+    #[test]
+    fn splitmix64_matches_the_reference_output() {
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(rng.next_u64(), 0x06C4_5D18_8009_454F);
+    }
+
+    #[test]
+    fn next_u8_is_the_high_byte_of_splitmix64() {
+        let mut rng = Rng::new(0);
+        let bytes: [u8; 3] = core::array::from_fn(|_| rng.next_u8());
+        assert_eq!(bytes, [0xE2, 0x6E, 0x06]);
+        // Frames don't advance SplitMix64
+        rng.tick();
+        assert_eq!(rng.get_state(), 0x9E37_79B9_7F4A_7C15u64.wrapping_mul(3));
+    }
+
+    #[test]
+    fn reseeding_repeats_the_sequence() {
+        let mut rng = Rng::new(42);
+        let first: [u8; 8] = core::array::from_fn(|_| rng.next_u8());
+        rng.reseed(42);
+        let second: [u8; 8] = core::array::from_fn(|_| rng.next_u8());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn vip_adds_the_page_byte_to_the_high_byte_of_r9() {
+        let mut rng = Rng::vip(0xAB_1234, identity_page());
+        assert!(rng.is_vip());
+        assert_eq!(rng.get_state(), 0x1234);
+        // R9 = 0x1235, 0x12 + M(0x0135)
+        assert_eq!(rng.next_u8(), 0x47);
+        assert_eq!(rng.get_state(), 0x4735);
+        // R9 = 0x4736, 0x47 + M(0x0136)
+        assert_eq!(rng.next_u8(), 0x7D);
+        rng.tick();
+        assert_eq!(rng.get_state(), 0x7D37);
+        // R9 = 0x7D38, 0x7D + M(0x0138)
+        assert_eq!(rng.next_u8(), 0xB5);
+    }
+
+    #[test]
+    fn vip_wraps_r9_and_keeps_its_algorithm_when_reseeded() {
+        let mut rng = Rng::vip(0xFFFF, identity_page());
+        assert_eq!(rng.next_u8(), 0x00);
+        assert_eq!(rng.get_state(), 0x0000);
+        rng.reseed(0x1_1234);
+        assert!(rng.is_vip());
+        assert_eq!(rng.get_state(), 0x1234);
+        assert_eq!(rng.next_u8(), 0x47);
+    }
+}
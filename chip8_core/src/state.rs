@@ -0,0 +1,131 @@
+use std::fs;
+
+use crate::error::Chip8Error;
+
+// Save state file header
+pub const STATE_MAGIC: &[u8; 4] = b"C8ST";
+// Bumped whenever the layout of a save state changes
+pub const STATE_VERSION: u8 = 3;
+
+pub fn read_state_file(path: &str) -> Result<Vec<u8>, Chip8Error> {
+    fs::read(path).map_err(|err| Chip8Error::InvalidState(format!("{}: {}", path, err)))
+}
+
+pub fn write_state_file(path: &str, state: &[u8]) -> Result<(), Chip8Error> {
+    fs::write(path, state).map_err(|err| Chip8Error::InvalidState(format!("{}: {}", path, err)))
+}
+
+// Serializes the fields of a versioned binary file, little endian
+// Used for save states and movies
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(magic: &[u8; 4], version: u8) -> Self {
+        let mut data = Vec::new();
+        data.extend_from_slice(magic);
+        data.push(version);
+        StateWriter { data }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+// Deserializes the fields written by `StateWriter`
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    // Builds the error reported for malformed data
+    error: fn(String) -> Chip8Error,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(
+        data: &'a [u8],
+        magic: &[u8; 4],
+        version: u8,
+        error: fn(String) -> Chip8Error,
+    ) -> Result<Self, Chip8Error> {
+        let mut reader = StateReader { data, error };
+        if reader.read_bytes(magic.len())? != magic {
+            return Err(error("unknown file header".to_string()));
+        }
+        let file_version = reader.read_u8()?;
+        if file_version != version {
+            return Err(error(format!(
+                "unsupported version {}, expected {}",
+                file_version, version
+            )));
+        }
+        Ok(reader)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Chip8Error> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Chip8Error> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.read_bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Chip8Error> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, Chip8Error> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Chip8Error> {
+        if len > self.data.len() {
+            return Err((self.error)("truncated file".to_string()));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    // Fails unless every field has been read
+    pub fn finish(self) -> Result<(), Chip8Error> {
+        if !self.data.is_empty() {
+            return Err((self.error)("unexpected data at the end".to_string()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::chip8::Chip8;
+use crate::constants;
+use crate::error::Chip8Error;
+use crate::opcode::{Opcode, OpcodeClass};
+
+// Layout of the trace lines
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    // Aligned columns, e.g. `12  202  7001  ADD V0, 0x01  I=000 DT=00 ST=00  V0=06`
+    Text,
+    // One JSON object per line
+    Jsonl,
+}
+
+impl TraceFormat {
+    pub const NAMES: [&'static str; 2] = ["text", "jsonl"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "jsonl" => Some(TraceFormat::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+// Instructions written to the trace, every instruction when empty
+#[derive(Default)]
+pub struct TraceFilter {
+    pub addresses: Option<RangeInclusive<u16>>,
+    pub classes: Vec<OpcodeClass>,
+}
+
+impl TraceFilter {
+    // Address range as `<start>-<end>`, both included, e.g. `0x200-0x2FF`
+    pub fn parse_range(range: &str) -> Option<RangeInclusive<u16>> {
+        let (start, end) = range.split_once('-')?;
+        let parse = |address: &str| match address.trim().strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16).ok(),
+            None => address.trim().parse::<u16>().ok(),
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        (start <= end).then_some(start..=end)
+    }
+
+    // Words that are not instructions have no class
+    fn matches(&self, pc: u16, opcode: Option<Opcode>) -> bool {
+        self.addresses
+            .as_ref()
+            .is_none_or(|addresses| addresses.contains(&pc))
+            && (self.classes.is_empty()
+                || opcode.is_some_and(|opcode| self.classes.contains(&opcode.class())))
+    }
+}
+
+// Registers compared before and after an instruction
+pub struct TraceState {
+    registers: [u8; constants::NUM_REGISTERS],
+    index_register: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+}
+
+impl TraceState {
+    pub fn of<const MEMORY: usize, const SCREEN: usize>(chip8: &Chip8<MEMORY, SCREEN>) -> Self {
+        TraceState {
+            registers: *chip8.get_registers(),
+            index_register: chip8.get_index_register(),
+            delay_timer: chip8.get_delay_timer(),
+            sound_timer: chip8.get_sound_timer(),
+        }
+    }
+}
+
+// Writes one line per executed instruction: cycle, address, opcode, mnemonic, the
+// registers it changed, I and the timers
+pub struct Tracer {
+    out: BufWriter<File>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    // Instructions executed so far, filtered out ones included
+    cycle: u64,
+    // Writing failed once, the rest of the trace is dropped
+    failed: bool,
+}
+
+impl Tracer {
+    pub fn create(path: &str, format: TraceFormat, filter: TraceFilter) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|err| format!("Could not create trace file {}: {}", path, err))?;
+
+        Ok(Tracer {
+            out: BufWriter::new(file),
+            format,
+            filter,
+            cycle: 0,
+            failed: false,
+        })
+    }
+
+    // Traces the instruction at `pc`, None when the word is not an instruction, flushing
+    // the trace when it failed so the file is complete when the emulator stops on the error
+    pub fn record(
+        &mut self,
+        pc: u16,
+        word: u16,
+        opcode: Option<Opcode>,
+        before: &TraceState,
+        after: &TraceState,
+        error: Option<&Chip8Error>,
+    ) {
+        self.cycle += 1;
+        if self.failed {
+            return;
+        }
+
+        let mut result = Ok(());
+        if self.filter.matches(pc, opcode) {
+            let line = self.line(pc, word, opcode, before, after, error);
+            result = writeln!(self.out, "{}", line);
+        }
+        if error.is_some() {
+            result = result.and_then(|_| self.out.flush());
+        }
+        if let Err(err) = result {
+            eprintln!("Could not write trace: {}", err);
+            self.failed = true;
+        }
+    }
+
+    fn line(
+        &self,
+        pc: u16,
+        word: u16,
+        opcode: Option<Opcode>,
+        before: &TraceState,
+        after: &TraceState,
+        error: Option<&Chip8Error>,
+    ) -> String {
+        let changed: Vec<(usize, u8)> = (0..constants::NUM_REGISTERS)
+            .filter(|&register| before.registers[register] != after.registers[register])
+            .map(|register| (register, after.registers[register]))
+            .collect();
+        let mnemonic = opcode.map_or_else(|| "unknown".to_string(), |opcode| opcode.to_string());
+        match self.format {
+            TraceFormat::Text => {
+                let mut line = format!(
+                    "{:>10}  {:03X}  {:04X}  {:<24}  I={:03X} DT={:02X} ST={:02X}",
+                    self.cycle,
+                    pc,
+                    word,
+                    mnemonic,
+                    after.index_register,
+                    after.delay_timer,
+                    after.sound_timer
+                );
+                if !changed.is_empty() {
+                    line.push(' ');
+                }
+                for (register, value) in &changed {
+                    line.push_str(&format!(" V{:X}={:02X}", register, value));
+                }
+                if let Some(error) = error {
+                    line.push_str(&format!("  ! {}", error));
+                }
+                line
+            }
+            TraceFormat::Jsonl => {
+                let changed: Vec<String> = changed
+                    .iter()
+                    .map(|(register, value)| format!("\"V{:X}\":{}", register, value))
+                    .collect();
+                let mut line = format!(
+                    "{{\"cycle\":{},\"pc\":{},\"opcode\":{},\"mnemonic\":\"{}\",\"changed\":{{{}}},\"i\":{},\"dt\":{},\"st\":{}",
+                    self.cycle,
+                    pc,
+                    word,
+                    mnemonic,
+                    changed.join(","),
+                    after.index_register,
+                    after.delay_timer,
+                    after.sound_timer
+                );
+                if let Some(error) = error {
+                    line.push_str(&format!(
+                        ",\"error\":\"{}\"",
+                        json_escape(&error.to_string())
+                    ));
+                }
+                line.push('}');
+                line
+            }
+        }
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::MovieKeypad;
+    use crate::quirks::Quirks;
+    use std::fs;
+
+    fn trace_file(test: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("chip8_trace_{}_{}", test, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // Runs the ROM until it fails and returns the trace lines
+    fn trace(test: &str, rom: &[u8], format: TraceFormat, filter: TraceFilter) -> Vec<String> {
+        let path = trace_file(test);
+        let mut chip8 =
+            Chip8::from_rom(rom, 8, constants::STACK_LEVELS, Quirks::default(), 0).unwrap();
+        chip8.set_tracer(Tracer::create(&path, format, filter).unwrap());
+        let mut keypad = MovieKeypad::new();
+        while chip8.step(&mut keypad).is_ok() {}
+        let trace = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        trace.lines().map(str::to_string).collect()
+    }
+
+    // V0 := 5, then 5121 which is not an instruction
+    const UNKNOWN: [u8; 4] = [0x60, 0x05, 0x51, 0x21];
+
+    #[test]
+    fn records_the_word_that_is_not_an_instruction() {
+        let lines = trace("text", &UNKNOWN, TraceFormat::Text, TraceFilter::default());
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("  V0=05"), "{}", lines[0]);
+        assert!(
+            lines[1].starts_with("         2  202  5121  unknown "),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[1].ends_with("  ! Unknown opcode 0x5121 at 0x202"),
+            "{}",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn records_the_word_that_is_not_an_instruction_as_json() {
+        let lines = trace(
+            "jsonl",
+            &UNKNOWN,
+            TraceFormat::Jsonl,
+            TraceFilter::default(),
+        );
+        assert_eq!(
+            lines[1],
+            "{\"cycle\":2,\"pc\":514,\"opcode\":20769,\"mnemonic\":\"unknown\",\"changed\":{},\
+             \"i\":0,\"dt\":0,\"st\":0,\"error\":\"Unknown opcode 0x5121 at 0x202\"}"
+        );
+    }
+
+    #[test]
+    fn filters_by_address_and_class() {
+        let filter = TraceFilter {
+            addresses: TraceFilter::parse_range("0x202-0x2FF"),
+            classes: Vec::new(),
+        };
+        let lines = trace("address", &UNKNOWN, TraceFormat::Text, filter);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("5121  unknown"));
+
+        let filter = TraceFilter {
+            addresses: None,
+            classes: vec![OpcodeClass::Alu],
+        };
+        let lines = trace("class", &UNKNOWN, TraceFormat::Text, filter);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("6005  LD V0, 0x05"));
+    }
+}
@@ -0,0 +1,69 @@
+// Boots ROMs in place through the API available without the `std` feature, on machines of
+// every size
+use chip8_core::{constants, Chip8, Chip8Error, KeyEvent, Keypad, Quirks};
+
+type SmallChip8 = Chip8<{ constants::MEMORY_IN_B }, { constants::LORES_SCREEN_SIZE }>;
+
+struct NoKeys;
+
+impl Keypad for NoKeys {
+    fn is_pressed(&self, _key: u8) -> bool {
+        false
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        None
+    }
+}
+
+// Built at compile time, the machine is never copied through the stack
+static mut SMALL: SmallChip8 = SmallChip8::empty();
+
+fn rom(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/../roms/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+fn run<const MEMORY: usize, const SCREEN: usize>(
+    chip8: &mut Chip8<MEMORY, SCREEN>,
+    rom: &[u8],
+    quirks: Quirks,
+) -> Result<(), Chip8Error> {
+    chip8.load_rom(rom, 8, constants::STACK_LEVELS, quirks, 0)?;
+    for _ in 0..60 {
+        chip8.run_frame(&mut NoKeys)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn small_machine_draws_like_the_full_one() {
+    let rom = rom("ibm_logo.ch8");
+    let mut full: Box<Chip8> = Box::new(Chip8::empty());
+    run(&mut full, &rom, Quirks::vip()).unwrap();
+    // The only access to the static, from a single test
+    let small = unsafe { &mut *std::ptr::addr_of_mut!(SMALL) };
+    run(small, &rom, Quirks::vip()).unwrap();
+    assert_eq!(small.get_screen(), full.get_screen());
+    assert!(small.get_screen().iter().any(|&pixel| pixel != 0));
+    assert_eq!(small.get_memory().len(), constants::MEMORY_IN_B);
+}
+
+#[test]
+fn small_machine_rejects_what_does_not_fit() {
+    let mut small = Box::new(SmallChip8::empty());
+    assert_eq!(
+        run(&mut small, &rom("ibm_logo.ch8"), Quirks::xochip()),
+        Err(Chip8Error::MemoryTooSmall {
+            size: constants::XO_CHIP_MEMORY_IN_B,
+            max_size: constants::MEMORY_IN_B
+        })
+    );
+    // 00FF right away
+    assert_eq!(
+        run(&mut small, &[0x00, 0xFF], Quirks::schip()),
+        Err(Chip8Error::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0x00FF
+        })
+    );
+}
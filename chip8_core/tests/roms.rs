@@ -0,0 +1,40 @@
+// Runs the bundled ROMs headless and compares the final screen and registers with the
+// golden dumps in tests/golden, `UPDATE_GOLDEN=1 cargo test` rewrites them
+#![cfg(feature = "std")]
+
+use chip8_core::headless::{Headless, RunLength, ScriptedKeypad};
+use chip8_core::quirks::Quirks;
+use chip8_core::rng::Rng;
+
+fn run(rom: &str, quirks: Quirks, frames: u64) -> String {
+    let rom_path = format!("{}/../roms/{}", env!("CARGO_MANIFEST_DIR"), rom);
+    let keypad = ScriptedKeypad::new("").unwrap();
+    let mut headless = Headless::new(&rom_path, 8, 16, quirks, keypad, false).unwrap();
+    headless.set_rng(Rng::new(0));
+    headless.run(RunLength::Frames(frames)).unwrap();
+    // Everything up to the memory dump, which only repeats the ROM
+    let dump = headless.dump();
+    dump[..dump.find("Memory:").unwrap()].to_string()
+}
+
+fn assert_golden(dump: &str, name: &str) {
+    let path = format!("{}/tests/golden/{}", env!("CARGO_MANIFEST_DIR"), name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, dump).unwrap();
+    }
+    let golden = std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {}", path, err));
+    assert_eq!(dump, golden, "dump differs from {}", path);
+}
+
+#[test]
+fn ibm_logo() {
+    assert_golden(&run("ibm_logo.ch8", Quirks::vip(), 60), "ibm_logo.txt");
+}
+
+#[test]
+fn opcode_test() {
+    assert_golden(
+        &run("test_opcode.ch8", Quirks::vip(), 120),
+        "test_opcode.txt",
+    );
+}
@@ -0,0 +1,690 @@
+// libretro core, so the emulator runs inside RetroArch and other libretro frontends
+// The frontend hands over the ROM, calls `retro_run` once per 60 Hz frame and presents the
+// frames and samples passed to its callbacks
+// Every pointer argument follows the contract of the libretro API
+#![allow(clippy::missing_safety_doc)]
+
+mod libretro;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_uint, c_void};
+use std::{ptr, slice};
+
+use chip8_core::{constants, Chip8, Chip8Error, KeyEvent, Keypad, Quirks};
+use libretro::*;
+use rand::random;
+
+const SAMPLE_RATE: usize = 44100;
+// Default of the instructions per second option
+const EMULATION_IPS: u128 = 500;
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE / constants::FRAMES_PER_SECOND as usize;
+// Amplitude of the beeper, as the 0.2 volume of the SDL frontend
+const VOLUME: i16 = 6554;
+const BEEP_FREQUENCY: f32 = 440.0;
+// Colors indexed by the XO-CHIP plane bits of a pixel, as in `media::Screen`
+const PALETTE: [u32; 4] = [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555];
+
+// RetroPad button of every key, digits 2, 4, 6 and 8 on the D-pad and 5 on A
+const JOYPAD: [c_uint; constants::NUM_KEYS] = [
+    RETRO_DEVICE_ID_JOYPAD_B,
+    RETRO_DEVICE_ID_JOYPAD_Y,
+    RETRO_DEVICE_ID_JOYPAD_UP,
+    RETRO_DEVICE_ID_JOYPAD_X,
+    RETRO_DEVICE_ID_JOYPAD_LEFT,
+    RETRO_DEVICE_ID_JOYPAD_A,
+    RETRO_DEVICE_ID_JOYPAD_RIGHT,
+    RETRO_DEVICE_ID_JOYPAD_L,
+    RETRO_DEVICE_ID_JOYPAD_DOWN,
+    RETRO_DEVICE_ID_JOYPAD_R,
+    RETRO_DEVICE_ID_JOYPAD_SELECT,
+    RETRO_DEVICE_ID_JOYPAD_START,
+    RETRO_DEVICE_ID_JOYPAD_L2,
+    RETRO_DEVICE_ID_JOYPAD_R2,
+    RETRO_DEVICE_ID_JOYPAD_L3,
+    RETRO_DEVICE_ID_JOYPAD_R3,
+];
+// Keyboard key of every key, with the layout of the SDL frontend, libretro key codes of
+// letters and digits are their ASCII codes
+const KEYBOARD: [u8; constants::NUM_KEYS] = *b"x123qweasdzc4rfv";
+const KEY_NAMES: [&CStr; constants::NUM_KEYS] = [
+    c"Key 0", c"Key 1", c"Key 2", c"Key 3", c"Key 4", c"Key 5", c"Key 6", c"Key 7", c"Key 8",
+    c"Key 9", c"Key A", c"Key B", c"Key C", c"Key D", c"Key E", c"Key F",
+];
+
+const QUIRKS_OPTION: &CStr = c"chip8_quirks";
+const IPS_OPTION: &CStr = c"chip8_ips";
+
+// Keypad fed with the input state polled once per frame
+struct RetroKeypad {
+    pressed: [bool; constants::NUM_KEYS],
+    events: VecDeque<KeyEvent>,
+}
+
+impl RetroKeypad {
+    fn set_keys(&mut self, keys: [bool; constants::NUM_KEYS]) {
+        for (key, &pressed) in keys.iter().enumerate() {
+            if self.pressed[key] != pressed {
+                self.events.push_back(if pressed {
+                    KeyEvent::Down(key as u8)
+                } else {
+                    KeyEvent::Up(key as u8)
+                });
+            }
+        }
+        self.pressed = keys;
+    }
+}
+
+impl Keypad for RetroKeypad {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.pressed.get(key as usize).copied().unwrap_or_default()
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+}
+
+// Beeper samples, a square wave or the XO-CHIP audio pattern as in `media::Beep`
+struct Beeper {
+    phase: f32,
+    // Position in the pattern [bits]
+    pattern_phase: f32,
+}
+
+impl Beeper {
+    // Fills interleaved stereo samples for the current frame
+    fn fill(&mut self, chip8: &Chip8, samples: &mut [i16]) {
+        const PATTERN_BITS: f32 = (constants::AUDIO_PATTERN_SIZE * 8) as f32;
+        let pattern_inc = chip8.get_pattern_rate() / SAMPLE_RATE as f32;
+
+        for frame in samples.chunks_mut(2) {
+            let high = match chip8.get_audio_pattern() {
+                Some(pattern) => {
+                    let bit = self.pattern_phase as usize;
+                    self.pattern_phase = (self.pattern_phase + pattern_inc) % PATTERN_BITS;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) > 0
+                }
+                None => {
+                    let high = self.phase <= 0.5;
+                    self.phase = (self.phase + BEEP_FREQUENCY / SAMPLE_RATE as f32) % 1.0;
+                    high
+                }
+            };
+            let sample = match (chip8.should_beep(), high) {
+                (false, _) => 0,
+                (true, true) => VOLUME,
+                (true, false) => -VOLUME,
+            };
+            frame.fill(sample);
+        }
+    }
+}
+
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+    // Logger of the frontend, messages are dropped when it has none
+    log: Option<RetroLogPrintf>,
+}
+
+impl Callbacks {
+    // Prints a line through the frontend's logger, as the frontend may have no console
+    fn log(&self, level: c_uint, message: &str) {
+        let Some(log) = self.log else {
+            return;
+        };
+        // The message is passed as an argument, so a `%` in it is never a format
+        if let Ok(message) = CString::new(format!("{}\n", message)) {
+            unsafe { log(level, c"%s".as_ptr(), message.as_ptr()) };
+        }
+    }
+}
+
+struct Core {
+    callbacks: Callbacks,
+    // Machine booted in place by `boot`, so the 74 KB are never on the stack
+    chip8: Chip8,
+    // False until a game is loaded
+    loaded: bool,
+    // Error that stopped the program, the last frame is shown until the game is reset
+    error: Option<Chip8Error>,
+    // ROM of the loaded game, booted again on reset
+    rom: Vec<u8>,
+    keypad: RetroKeypad,
+    beeper: Beeper,
+    // XRGB8888 pixels of the frame
+    frame: [u32; constants::SCREEN_SIZE],
+    samples: [i16; SAMPLES_PER_FRAME * 2],
+}
+
+// The frontend calls into the core from a single thread
+thread_local! {
+    static CORE: RefCell<Core> = const {
+        RefCell::new(Core {
+        callbacks: Callbacks {
+            environment: None,
+            video_refresh: None,
+            audio_sample_batch: None,
+            input_poll: None,
+            input_state: None,
+            log: None,
+        },
+        chip8: Chip8::empty(),
+        loaded: false,
+        error: None,
+        rom: Vec::new(),
+        keypad: RetroKeypad {
+            pressed: [false; constants::NUM_KEYS],
+            events: VecDeque::new(),
+        },
+        beeper: Beeper {
+            phase: 0.0,
+            pattern_phase: 0.0,
+        },
+        frame: [0; constants::SCREEN_SIZE],
+        samples: [0; SAMPLES_PER_FRAME * 2],
+    })
+    };
+}
+
+impl Core {
+    fn environment(&self, cmd: c_uint, data: *mut c_void) -> bool {
+        match self.callbacks.environment {
+            Some(environment) => unsafe { environment(cmd, data) },
+            None => false,
+        }
+    }
+
+    // Selected choice of a core option
+    fn option(&self, key: &CStr) -> Option<String> {
+        let mut variable = RetroVariable {
+            key: key.as_ptr(),
+            value: ptr::null(),
+        };
+        let found = self.environment(
+            RETRO_ENVIRONMENT_GET_VARIABLE,
+            &mut variable as *mut RetroVariable as *mut c_void,
+        );
+        if !found || variable.value.is_null() {
+            return None;
+        }
+        let value = unsafe { CStr::from_ptr(variable.value) };
+        Some(value.to_string_lossy().into_owned())
+    }
+
+    // Boots the ROM with the quirks and speed selected in the core options
+    fn boot(&mut self) -> Result<(), Chip8Error> {
+        let quirks = self
+            .option(QUIRKS_OPTION)
+            .and_then(|preset| Quirks::from_preset(&preset))
+            .unwrap_or_default();
+        let emulation_ips = self
+            .option(IPS_OPTION)
+            .and_then(|ips| ips.parse::<u128>().ok())
+            .unwrap_or(EMULATION_IPS);
+        let cycles_per_frame = ((emulation_ips + constants::FRAMES_PER_SECOND / 2)
+            / constants::FRAMES_PER_SECOND)
+            .max(1) as u32;
+
+        self.chip8.load_rom(
+            &self.rom,
+            cycles_per_frame,
+            constants::STACK_LEVELS,
+            quirks,
+            random::<u64>(),
+        )?;
+        self.loaded = true;
+        self.error = None;
+        self.keypad.pressed = [false; constants::NUM_KEYS];
+        self.keypad.events.clear();
+        Ok(())
+    }
+
+    // Keys held on the first RetroPad or on the keyboard
+    fn poll_keys(&self) -> [bool; constants::NUM_KEYS] {
+        let mut keys = [false; constants::NUM_KEYS];
+        let (Some(input_poll), Some(input_state)) =
+            (self.callbacks.input_poll, self.callbacks.input_state)
+        else {
+            return keys;
+        };
+        unsafe {
+            input_poll();
+            for (key, pressed) in keys.iter_mut().enumerate() {
+                *pressed = input_state(0, RETRO_DEVICE_JOYPAD, 0, JOYPAD[key]) != 0
+                    || input_state(0, RETRO_DEVICE_KEYBOARD, 0, KEYBOARD[key] as c_uint) != 0;
+            }
+        }
+        keys
+    }
+
+    fn run_frame(&mut self) {
+        let keys = self.poll_keys();
+        self.keypad.set_keys(keys);
+        if !self.loaded {
+            return;
+        }
+        let chip8 = &mut self.chip8;
+        if self.error.is_none() && !chip8.has_exited() {
+            if let Err(err) = chip8.run_frame(&mut self.keypad) {
+                self.callbacks
+                    .log(RETRO_LOG_ERROR, &format!("Error: {}", err));
+                self.error = Some(err);
+            }
+        }
+
+        // Present the frame
+        let width = chip8.get_screen_width();
+        let height = chip8.get_screen_height();
+        for (pixel, &planes) in self.frame.iter_mut().zip(chip8.get_screen()) {
+            *pixel = PALETTE[planes as usize & 0x03];
+        }
+        if let Some(video_refresh) = self.callbacks.video_refresh {
+            unsafe {
+                video_refresh(
+                    self.frame.as_ptr() as *const c_void,
+                    width as c_uint,
+                    height as c_uint,
+                    width * 4,
+                )
+            };
+        }
+
+        // Audio media, silent once the program stopped
+        if self.error.is_some() {
+            self.samples.fill(0);
+        } else {
+            self.beeper.fill(chip8, &mut self.samples);
+        }
+        if let Some(audio_sample_batch) = self.callbacks.audio_sample_batch {
+            unsafe { audio_sample_batch(self.samples.as_ptr(), SAMPLES_PER_FRAME) };
+        }
+    }
+
+    // Size of a state of the loaded game, see `retro_serialize_size`
+    fn serialize_size(&self) -> usize {
+        if !self.loaded {
+            return 0;
+        }
+        let free_levels = self.chip8.get_stack_depth() - self.chip8.get_stack().len();
+        4 + self.chip8.save_state().len() + free_levels * 2
+    }
+}
+
+fn with_core<T>(body: impl FnOnce(&mut Core) -> T) -> T {
+    CORE.with(|core| body(&mut core.borrow_mut()))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: RetroEnvironment) {
+    with_core(|core| {
+        core.callbacks.environment = Some(environment);
+        let mut log_callback = RetroLogCallback { log: None };
+        if core.environment(
+            RETRO_ENVIRONMENT_GET_LOG_INTERFACE,
+            &mut log_callback as *mut RetroLogCallback as *mut c_void,
+        ) {
+            core.callbacks.log = log_callback.log;
+        }
+        let mut variables = [
+            RetroVariable {
+                key: QUIRKS_OPTION.as_ptr(),
+                value: c"Quirks; cowgod|vip|chip48|schip|xochip".as_ptr(),
+            },
+            RetroVariable {
+                key: IPS_OPTION.as_ptr(),
+                value: c"Instructions per second; 500|1000|2000|5000|10000|30000|100|200|300|400"
+                    .as_ptr(),
+            },
+            RetroVariable {
+                key: ptr::null(),
+                value: ptr::null(),
+            },
+        ];
+        core.environment(
+            RETRO_ENVIRONMENT_SET_VARIABLES,
+            variables.as_mut_ptr() as *mut c_void,
+        );
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: RetroVideoRefresh) {
+    with_core(|core| core.callbacks.video_refresh = Some(video_refresh));
+}
+
+// Samples are sent a frame at a time through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: RetroAudioSample) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: RetroAudioSampleBatch) {
+    with_core(|core| core.callbacks.audio_sample_batch = Some(audio_sample_batch));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: RetroInputPoll) {
+    with_core(|core| core.callbacks.input_poll = Some(input_poll));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: RetroInputState) {
+    with_core(|core| core.callbacks.input_state = Some(input_state));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    with_core(|core| {
+        core.loaded = false;
+        core.rom.clear();
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+    *info = RetroSystemInfo {
+        library_name: c"CHIP-8".as_ptr(),
+        library_version: VERSION.as_ptr() as *const c_char,
+        valid_extensions: c"ch8|c8|sc8|xo8|rom".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: constants::SCREEN_WIDTH as c_uint,
+            base_height: constants::SCREEN_HEIGHT as c_uint,
+            max_width: constants::HIRES_SCREEN_WIDTH as c_uint,
+            max_height: constants::HIRES_SCREEN_HEIGHT as c_uint,
+            aspect_ratio: 2.0,
+        },
+        timing: RetroSystemTiming {
+            fps: constants::FRAMES_PER_SECOND as f64,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+// The keypad is the only input device
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+// Boots the game again, with the current core options
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core(|core| {
+        if core.loaded {
+            if let Err(err) = core.boot() {
+                core.callbacks
+                    .log(RETRO_LOG_ERROR, &format!("Error: {}", err));
+            }
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    with_core(Core::run_frame);
+}
+
+// Save states are prefixed with their length and padded as if every level of the stack
+// depth were in use, so the size stays the same for the whole game whatever the program
+// calls: libretro expects one size for every state of a game, for rewinding and netplay,
+// and the padding of up to 2 bytes per free level is intended
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    with_core(|core| core.serialize_size())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    with_core(|core| {
+        let size_needed = core.serialize_size();
+        if !core.loaded || data.is_null() || size < size_needed {
+            return false;
+        }
+        let state = core.chip8.save_state();
+        let out = slice::from_raw_parts_mut(data as *mut u8, size);
+        out.fill(0);
+        out[..4].copy_from_slice(&(state.len() as u32).to_le_bytes());
+        out[4..4 + state.len()].copy_from_slice(&state);
+        true
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    with_core(|core| {
+        if !core.loaded || data.is_null() || size < 4 {
+            return false;
+        }
+        let data = slice::from_raw_parts(data as *const u8, size);
+        let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let Some(state) = data[4..].get(..len) else {
+            return false;
+        };
+        match core.chip8.load_state(state) {
+            Ok(()) => {
+                core.error = None;
+                true
+            }
+            Err(err) => {
+                core.callbacks.log(RETRO_LOG_WARN, &err.to_string());
+                false
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+    if game.data.is_null() {
+        return false;
+    }
+    let rom = slice::from_raw_parts(game.data as *const u8, game.size);
+
+    with_core(|core| {
+        let mut pixel_format = RETRO_PIXEL_FORMAT_XRGB8888;
+        if !core.environment(
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+            &mut pixel_format as *mut c_uint as *mut c_void,
+        ) {
+            core.callbacks.log(
+                RETRO_LOG_ERROR,
+                "XRGB8888 pixels are not supported by the frontend",
+            );
+            return false;
+        }
+        let mut descriptors: Vec<RetroInputDescriptor> = JOYPAD
+            .iter()
+            .zip(KEY_NAMES)
+            .map(|(&id, name)| RetroInputDescriptor {
+                port: 0,
+                device: RETRO_DEVICE_JOYPAD,
+                index: 0,
+                id,
+                description: name.as_ptr(),
+            })
+            .collect();
+        descriptors.push(RetroInputDescriptor {
+            port: 0,
+            device: 0,
+            index: 0,
+            id: 0,
+            description: ptr::null(),
+        });
+        core.environment(
+            RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+            descriptors.as_mut_ptr() as *mut c_void,
+        );
+
+        core.rom = rom.to_vec();
+        match core.boot() {
+            Ok(()) => true,
+            Err(err) => {
+                core.callbacks
+                    .log(RETRO_LOG_ERROR, &format!("Error: {}", err));
+                core.loaded = false;
+                false
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    with_core(|core| {
+        core.loaded = false;
+        core.rom.clear();
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+// Memory is not exposed, as writes from the frontend would bypass `Chip8`
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frontend accepting the pixel format and nothing else, so the options keep their
+    // defaults
+    unsafe extern "C" fn environment(cmd: c_uint, _data: *mut c_void) -> bool {
+        cmd == RETRO_ENVIRONMENT_SET_PIXEL_FORMAT
+    }
+
+    // Loads the game on the core of the test thread
+    fn load(rom: &[u8]) {
+        retro_set_environment(environment);
+        let game = RetroGameInfo {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+        assert!(unsafe { retro_load_game(&game) });
+    }
+
+    fn serialize() -> Vec<u8> {
+        let mut state = vec![0xAA; retro_serialize_size()];
+        assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+        state
+    }
+
+    fn unserialize(state: &[u8]) -> bool {
+        unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) }
+    }
+
+    // Three nested calls, then a jump to itself
+    const CALLS: [u8; 8] = [0x22, 0x02, 0x22, 0x04, 0x22, 0x06, 0x12, 0x06];
+
+    #[test]
+    fn keypad_reports_changed_keys() {
+        let mut keypad = RetroKeypad {
+            pressed: [false; constants::NUM_KEYS],
+            events: VecDeque::new(),
+        };
+        let mut keys = [false; constants::NUM_KEYS];
+        keys[0x1] = true;
+        keys[0xF] = true;
+        keypad.set_keys(keys);
+        keys[0x1] = false;
+        keypad.set_keys(keys);
+        keypad.set_keys(keys);
+        assert!(keypad.is_pressed(0xF) && !keypad.is_pressed(0x1));
+        assert!(!keypad.is_pressed(0x10));
+        let events: Vec<KeyEvent> = std::iter::from_fn(|| keypad.next_event()).collect();
+        assert_eq!(
+            events,
+            [KeyEvent::Down(0x1), KeyEvent::Down(0xF), KeyEvent::Up(0x1)]
+        );
+    }
+
+    #[test]
+    fn states_round_trip_with_a_constant_size() {
+        load(&CALLS);
+        let size = retro_serialize_size();
+        retro_run();
+        assert_eq!(with_core(|core| core.chip8.get_stack().len()), 3);
+        assert_eq!(retro_serialize_size(), size);
+        let state = serialize();
+
+        retro_reset();
+        assert_eq!(with_core(|core| core.chip8.get_stack().len()), 0);
+        assert!(unserialize(&state));
+        assert_eq!(
+            with_core(|core| core.chip8.get_stack().to_vec()),
+            [0x202, 0x204, 0x206]
+        );
+        assert_eq!(serialize(), state);
+    }
+
+    #[test]
+    fn undersized_buffers_are_refused() {
+        let mut state = vec![0; 16];
+        assert!(!unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+        load(&CALLS);
+        let mut state = vec![0; retro_serialize_size() - 1];
+        assert!(!unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+    }
+
+    #[test]
+    fn truncated_states_are_refused() {
+        load(&CALLS);
+        let state = serialize();
+        assert!(!unserialize(&state[..3]));
+        // Length prefix claiming more bytes than given
+        let mut truncated = state.clone();
+        truncated[..4].copy_from_slice(&(state.len() as u32).to_le_bytes());
+        assert!(!unserialize(&truncated));
+        assert!(unserialize(&state));
+    }
+}
@@ -0,0 +1,171 @@
+// Browser build of the emulator, exported as plain C functions for JavaScript
+// The page copies a ROM into `chip8_rom_buffer`, boots it with `chip8_load_rom`, then on
+// every 60 Hz frame sets the keys, calls `chip8_run_frame` and draws `chip8_screen`
+// Failures return the exit code of the `Chip8Error`, 0 on success
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use chip8_core::{constants, Chip8, KeyEvent, Keypad, Quirks};
+
+// Keypad fed with the key state set from JavaScript
+struct WebKeypad {
+    pressed: [bool; constants::NUM_KEYS],
+    events: VecDeque<KeyEvent>,
+}
+
+impl Keypad for WebKeypad {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.pressed.get(key as usize).copied().unwrap_or_default()
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+}
+
+struct Web {
+    // Machine booted in place by `chip8_load_rom`, so the 74 KB are never on the stack
+    chip8: Chip8,
+    // False until a ROM is loaded
+    loaded: bool,
+    keypad: WebKeypad,
+    // ROM bytes written by JavaScript before `chip8_load_rom`
+    rom: Vec<u8>,
+}
+
+// wasm32 is single threaded, the page owns a single emulator
+thread_local! {
+    static WEB: RefCell<Web> = const {
+        RefCell::new(Web {
+            chip8: Chip8::empty(),
+            loaded: false,
+            keypad: WebKeypad {
+                pressed: [false; constants::NUM_KEYS],
+                events: VecDeque::new(),
+            },
+            rom: Vec::new(),
+        })
+    };
+}
+
+// Buffer of the given size for JavaScript to copy the ROM bytes into
+#[no_mangle]
+pub extern "C" fn chip8_rom_buffer(len: usize) -> *mut u8 {
+    WEB.with(|web| {
+        let rom = &mut web.borrow_mut().rom;
+        *rom = vec![0; len];
+        rom.as_mut_ptr()
+    })
+}
+
+// Boots the ROM in the buffer with the quirks of `Quirks::PRESETS[preset]`, CXNN starts
+// from the given seed
+#[no_mangle]
+pub extern "C" fn chip8_load_rom(preset: u32, cycles_per_frame: u32, seed: u32) -> i32 {
+    let quirks = Quirks::PRESETS
+        .get(preset as usize)
+        .and_then(|name| Quirks::from_preset(name))
+        .unwrap_or_default();
+    WEB.with(|web| {
+        let web = &mut *web.borrow_mut();
+        let loaded = web.chip8.load_rom(
+            &web.rom,
+            cycles_per_frame.max(1),
+            constants::STACK_LEVELS,
+            quirks,
+            seed as u64,
+        );
+        match loaded {
+            Ok(()) => {
+                web.loaded = true;
+                web.keypad.pressed = [false; constants::NUM_KEYS];
+                web.keypad.events.clear();
+                0
+            }
+            Err(err) => err.exit_code(),
+        }
+    })
+}
+
+// Runs one 60 Hz frame, nothing happens before a ROM is loaded
+#[no_mangle]
+pub extern "C" fn chip8_run_frame() -> i32 {
+    WEB.with(|web| {
+        let web = &mut *web.borrow_mut();
+        if !web.loaded {
+            return 0;
+        }
+        match web.chip8.run_frame(&mut web.keypad) {
+            Ok(()) => 0,
+            Err(err) => err.exit_code(),
+        }
+    })
+}
+
+// Pixels of the current resolution, row by row, one byte of XO-CHIP plane bits per pixel
+// The pointer stays valid until the next call into the emulator
+#[no_mangle]
+pub extern "C" fn chip8_screen() -> *const u8 {
+    WEB.with(|web| {
+        let web = web.borrow();
+        if web.loaded {
+            web.chip8.get_screen().as_ptr()
+        } else {
+            std::ptr::null()
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_screen_width() -> u32 {
+    with_chip8(|chip8| chip8.get_screen_width() as u32)
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_screen_height() -> u32 {
+    with_chip8(|chip8| chip8.get_screen_height() as u32)
+}
+
+// Presses or releases a key (0x0 - 0xF)
+#[no_mangle]
+pub extern "C" fn chip8_set_key(key: u32, pressed: u32) {
+    WEB.with(|web| {
+        let keypad = &mut web.borrow_mut().keypad;
+        let pressed = pressed != 0;
+        if let Some(state) = keypad.pressed.get_mut(key as usize) {
+            if *state != pressed {
+                *state = pressed;
+                keypad.events.push_back(if pressed {
+                    KeyEvent::Down(key as u8)
+                } else {
+                    KeyEvent::Up(key as u8)
+                });
+            }
+        }
+    })
+}
+
+// 1 while the sound timer runs
+#[no_mangle]
+pub extern "C" fn chip8_is_beeping() -> u32 {
+    with_chip8(|chip8| chip8.should_beep() as u32)
+}
+
+// 1 once the program stopped itself with 00FD
+#[no_mangle]
+pub extern "C" fn chip8_has_exited() -> u32 {
+    with_chip8(|chip8| chip8.has_exited() as u32)
+}
+
+// Reads the loaded machine, 0 before a ROM is loaded
+fn with_chip8(read: impl Fn(&Chip8) -> u32) -> u32 {
+    WEB.with(|web| {
+        let web = web.borrow();
+        if web.loaded {
+            read(&web.chip8)
+        } else {
+            0
+        }
+    })
+}
@@ -0,0 +1,77 @@
+// Headless tests of the wasm exports, run after building the wasm file:
+//   cargo build -p chip8_wasm --release --target wasm32-unknown-unknown
+//   node chip8_wasm/test/headless.mjs
+import assert from "node:assert/strict";
+import { readFileSync } from "node:fs";
+import { fileURLToPath } from "node:url";
+
+const root = fileURLToPath(new URL("../../", import.meta.url));
+const wasmPath =
+  process.argv[2] ?? `${root}target/wasm32-unknown-unknown/release/chip8_wasm.wasm`;
+const { instance } = await WebAssembly.instantiate(readFileSync(wasmPath));
+const chip8 = instance.exports;
+
+const PRESETS = { vip: 0, chip48: 1, schip: 2, xochip: 3, cowgod: 4 };
+// Pixels lit by the IBM logo once it is fully drawn
+const IBM_LOGO_PIXELS = 208;
+
+function load(rom, preset = PRESETS.vip) {
+  const buffer = chip8.chip8_rom_buffer(rom.length);
+  new Uint8Array(chip8.memory.buffer, buffer, rom.length).set(rom);
+  return chip8.chip8_load_rom(preset, 8, 1234);
+}
+
+function screen() {
+  const width = chip8.chip8_screen_width();
+  const height = chip8.chip8_screen_height();
+  return new Uint8Array(chip8.memory.buffer, chip8.chip8_screen(), width * height);
+}
+
+function test(name, body) {
+  body();
+  console.log(`ok - ${name}`);
+}
+
+test("nothing runs before a ROM is loaded", () => {
+  assert.equal(chip8.chip8_run_frame(), 0);
+  assert.equal(chip8.chip8_screen_width(), 0);
+  assert.equal(chip8.chip8_is_beeping(), 0);
+});
+
+test("IBM logo is drawn", () => {
+  assert.equal(load(readFileSync(`${root}roms/ibm_logo.ch8`)), 0);
+  for (let frame = 0; frame < 30; frame++) {
+    assert.equal(chip8.chip8_run_frame(), 0);
+  }
+  assert.equal(chip8.chip8_screen_width(), 64);
+  assert.equal(chip8.chip8_screen_height(), 32);
+  const lit = screen().filter((pixel) => pixel !== 0).length;
+  assert.equal(lit, IBM_LOGO_PIXELS);
+});
+
+test("ROM too large for memory is rejected", () => {
+  assert.equal(load(new Uint8Array(0x10000)), 3);
+});
+
+test("key press and release resume FX0A", () => {
+  // V0 := key, ST := V0, then loop
+  assert.equal(load([0xf0, 0x0a, 0xf0, 0x18, 0x12, 0x04]), 0);
+  chip8.chip8_run_frame();
+  assert.equal(chip8.chip8_is_beeping(), 0);
+  chip8.chip8_set_key(0x5, 1);
+  chip8.chip8_run_frame();
+  chip8.chip8_set_key(0x5, 0);
+  chip8.chip8_run_frame();
+  assert.equal(chip8.chip8_is_beeping(), 1);
+});
+
+test("00FD exits the program", () => {
+  assert.equal(load([0x00, 0xfd], PRESETS.schip), 0);
+  assert.equal(chip8.chip8_run_frame(), 0);
+  assert.equal(chip8.chip8_has_exited(), 1);
+});
+
+test("unknown opcode reports its exit code", () => {
+  assert.equal(load([0xff, 0xff]), 0);
+  assert.equal(chip8.chip8_run_frame(), 6);
+});
@@ -0,0 +1,727 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use crate::expr::Expr;
+use chip8_core::asm;
+use chip8_core::chip8::{self, MemoryAccess};
+use chip8_core::constants;
+use chip8_core::error::Chip8Error;
+use chip8_core::headless;
+use chip8_core::movie::MovieKeypad;
+use chip8_core::opcode::{decode, Opcode};
+use chip8_core::quirks::Quirks;
+use chip8_core::rng::Rng;
+use chip8_core::state;
+use chip8_core::trace::Tracer;
+
+// Most instructions `continue`, `until` and `next` run before giving control back
+const RUN_LIMIT: u64 = 10_000_000;
+
+const HELP: &str = "\
+Commands, an empty line repeats the last one:
+  s, step [n]            execute n instructions, default 1
+  n, next [n]            like step, running 2NNN calls as a single instruction
+  c, continue            run until a breakpoint, FX0A or 00FD
+  u, until <addr>        run until the program counter reaches the address
+  b, break [addr]        set a breakpoint, or list them without an address
+  b, break <addr> if <expr>
+                         set a breakpoint that only stops when the condition holds
+  d, delete <addr>       remove a breakpoint
+  w, watch <addr> [len]  stop after instructions writing the memory, default 1 byte
+  rwatch <addr> [len]    stop after instructions reading the memory
+  awatch <addr> [len]    stop after instructions reading or writing the memory
+  when <expr>            stop when the condition becomes true
+  watches                list the watchpoints and conditions
+  unwatch <n>            remove a watchpoint or condition by number
+  r, regs                show the registers, timers and stack
+  set <reg> <value>      change V0-VF, I, PC, DT or ST
+  x <addr> [len]         show memory, default 64 bytes
+  poke <addr> <byte>..   change memory
+  l, list [addr]         disassemble around the program counter or the address
+  press <key>            press a key of the keypad, in hex
+  release <key>          release a key of the keypad
+  screen                 show the screen
+  h, help                show this help
+  q, quit                exit the debugger
+Addresses and values are decimal, 0x hex, 0b binary, labels of the symbol file, or
+  conditions without spaces, e.g. `sprites+8` or `I`
+Conditions combine V0-VF, I, PC, DT, ST, memory bytes [addr], numbers and labels with
+  || && == != < <= > >= | ^ & + - ! and parentheses, e.g. `V3 == 0x10 && I > 0x300`";
+
+// Why a run stopped
+enum Stop {
+    Done,
+    Breakpoint(u16),
+    Watchpoint {
+        number: u32,
+        access: MemoryAccess,
+        pc: u16,
+    },
+    Condition(u32),
+    WaitingForKey,
+    Exited,
+    Limit,
+    Error(Chip8Error),
+}
+
+// Expression with the text it was parsed from
+struct Condition {
+    expr: Expr,
+    text: String,
+}
+
+enum Watch {
+    // Memory addresses, stopping after instructions reading and/or writing them
+    Memory {
+        range: Range<usize>,
+        read: bool,
+        write: bool,
+    },
+    // Stops when the condition becomes true, `last` holds its value before the
+    // last instruction
+    Condition {
+        condition: Condition,
+        last: bool,
+    },
+}
+
+// Interactive debugger driving a `Chip8` from commands read on stdin
+pub struct Debugger {
+    chip8: chip8::Chip8,
+    keypad: MovieKeypad,
+    // Keys held down with `press`
+    keys: u16,
+    // Breakpoints by address, with an optional condition
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    // Watchpoints and conditions by number
+    watches: BTreeMap<u32, Watch>,
+    next_watch: u32,
+    // Label addresses by upper case name, and the label shown for an address
+    symbols: BTreeMap<String, u16>,
+    labels: BTreeMap<u16, String>,
+    // Instructions executed so far, timers tick every `cycles_per_frame` of them
+    cycles: u64,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new(
+        rom_path: &str,
+        cycles_per_frame: u32,
+        stack_depth: usize,
+        quirks: Quirks,
+        debug: bool,
+    ) -> Result<Self, Chip8Error> {
+        let mut chip8 = chip8::Chip8::new(rom_path, cycles_per_frame, stack_depth, quirks, debug)?;
+        chip8.set_memory_log(true);
+
+        Ok(Debugger {
+            chip8,
+            keypad: MovieKeypad::new(),
+            keys: 0,
+            breakpoints: BTreeMap::new(),
+            watches: BTreeMap::new(),
+            next_watch: 1,
+            symbols: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            cycles: 0,
+            last_command: String::new(),
+        })
+    }
+
+    // Boots into the snapshot stored in the given file
+    pub fn load_state_file(&mut self, state_path: &str) -> Result<(), Chip8Error> {
+        let state = state::read_state_file(state_path)?;
+        self.chip8.load_state(&state)
+    }
+
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.chip8.set_rng(rng);
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.chip8.set_tracer(tracer);
+    }
+
+    // Reads labels from a symbol file written by `assemble --symbols`, one
+    // `<address> <name>` per line
+    pub fn load_symbols(&mut self, path: &str) -> Result<(), String> {
+        let symbols = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read symbol file {}: {}", path, err))?;
+        for (line_num, line) in symbols.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || format!("Invalid symbol file line {}: `{}`", line_num + 1, line);
+            let mut parts = line.split_whitespace();
+            let address = parts
+                .next()
+                .and_then(asm::parse_number)
+                .and_then(|address| u16::try_from(address).ok())
+                .ok_or_else(invalid)?;
+            let name = parts.next().ok_or_else(invalid)?;
+            if parts.next().is_some() {
+                return Err(invalid());
+            }
+            self.symbols.insert(name.to_uppercase(), address);
+            self.labels
+                .entry(address)
+                .or_insert_with(|| name.to_string());
+        }
+        Ok(())
+    }
+
+    // Reads and runs commands until `quit` or the end of the input
+    pub fn run(&mut self) {
+        println!("Type `help` for the list of commands");
+        self.print_location();
+
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("(chip8) ");
+            io::stdout().flush().unwrap_or_default();
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => break,
+            };
+
+            let line = if line.trim().is_empty() {
+                self.last_command.clone()
+            } else {
+                line.trim().to_string()
+            };
+            if line.is_empty() {
+                continue;
+            }
+            match self.execute(&line) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(err) => println!("{}", err),
+            }
+            self.last_command = line;
+        }
+    }
+
+    // Runs a single command, returns whether the debugger should exit
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let mut parts = line.split_whitespace();
+        let command = parts.next().unwrap_or_default().to_lowercase();
+        let args: Vec<&str> = parts.collect();
+
+        match command.as_str() {
+            "s" | "step" => {
+                let count = self.count_arg(&args)?;
+                match self.run_until(count, |_| false) {
+                    Stop::Limit => self.report(Stop::Done),
+                    stop => self.report(stop),
+                }
+            }
+            "n" | "next" => {
+                let count = self.count_arg(&args)?;
+                for _ in 0..count {
+                    let stop = self.step_over();
+                    if !matches!(stop, Stop::Done) {
+                        self.report(stop);
+                        return Ok(false);
+                    }
+                }
+                self.report(Stop::Done);
+            }
+            "c" | "continue" => {
+                let stop = self.run_until(RUN_LIMIT, |_| false);
+                self.report(stop);
+            }
+            "u" | "until" => {
+                let address = self.address_arg(&args, 0)?;
+                let stop =
+                    self.run_until(RUN_LIMIT, |chip8| chip8.get_program_counter() == address);
+                self.report(stop);
+            }
+            "b" | "break" => {
+                if args.is_empty() {
+                    if self.breakpoints.is_empty() {
+                        println!("No breakpoints");
+                    }
+                    for (&address, condition) in &self.breakpoints {
+                        match condition {
+                            Some(condition) => println!(
+                                "Breakpoint at {} if {}",
+                                self.describe(address),
+                                condition.text
+                            ),
+                            None => println!("Breakpoint at {}", self.describe(address)),
+                        }
+                    }
+                } else {
+                    let address = self.address_arg(&args, 0)?;
+                    let condition = match args.get(1) {
+                        Some(&"if") => Some(self.condition(&args[2..])?),
+                        Some(_) => return Err("Usage: break <addr> [if <expr>]".to_string()),
+                        None => None,
+                    };
+                    self.breakpoints.insert(address, condition);
+                    println!("Breakpoint set at {}", self.describe(address));
+                }
+            }
+            "d" | "delete" => {
+                let address = self.address_arg(&args, 0)?;
+                if self.breakpoints.remove(&address).is_none() {
+                    return Err(format!("No breakpoint at 0x{:03X}", address));
+                }
+                println!("Breakpoint removed at {}", self.describe(address));
+            }
+            "w" | "watch" | "rwatch" | "awatch" => {
+                let address = self.address_arg(&args, 0)?;
+                let length = match args.get(1) {
+                    Some(length) => self.value(length)?,
+                    None => 1,
+                };
+                if length == 0 {
+                    return Err("Watchpoint length must be at least 1".to_string());
+                }
+                let range = self.memory_range(address, length)?;
+                let (read, write) = match command.as_str() {
+                    "rwatch" => (true, false),
+                    "awatch" => (true, true),
+                    _ => (false, true),
+                };
+                let number = self.add_watch(Watch::Memory { range, read, write });
+                println!("Watchpoint {} set", number);
+            }
+            "when" => {
+                let condition = self.condition(&args)?;
+                let last = condition.expr.is_true(&self.chip8);
+                let number = self.add_watch(Watch::Condition { condition, last });
+                println!("Condition {} set", number);
+            }
+            "watches" => {
+                if self.watches.is_empty() {
+                    println!("No watchpoints");
+                }
+                for (number, watch) in &self.watches {
+                    match watch {
+                        Watch::Memory { range, read, write } => println!(
+                            "{}: {} 0x{:03X} - 0x{:03X}",
+                            number,
+                            access_name(*read, *write),
+                            range.start,
+                            range.end - 1
+                        ),
+                        Watch::Condition { condition, .. } => {
+                            println!("{}: when {}", number, condition.text)
+                        }
+                    }
+                }
+            }
+            "unwatch" => {
+                let number = args
+                    .first()
+                    .and_then(|number| number.parse::<u32>().ok())
+                    .ok_or_else(|| "Usage: unwatch <n>".to_string())?;
+                if self.watches.remove(&number).is_none() {
+                    return Err(format!("No watchpoint {}", number));
+                }
+            }
+            "r" | "regs" => self.print_registers(),
+            "set" => self.set(&args)?,
+            "x" => {
+                let address = self.address_arg(&args, 0)?;
+                // The default length stops at the end of memory
+                let length = match args.get(1) {
+                    Some(length) => self.value(length)?,
+                    None => (self.chip8.get_memory().len() as u64)
+                        .saturating_sub(address as u64)
+                        .min(64),
+                };
+                self.print_memory(self.memory_range(address, length)?)?;
+            }
+            "poke" => {
+                let address = self.address_arg(&args, 0)?;
+                if args.len() < 2 {
+                    return Err("Usage: poke <addr> <byte>..".to_string());
+                }
+                let bytes = args[1..]
+                    .iter()
+                    .map(|byte| self.byte(byte))
+                    .collect::<Result<Vec<u8>, String>>()?;
+                let range = self.memory_range(address, bytes.len() as u64)?;
+                for (address, &byte) in range.zip(&bytes) {
+                    self.chip8.set_memory(address, byte);
+                }
+            }
+            "l" | "list" => {
+                let address = match args.first() {
+                    Some(_) => self.address_arg(&args, 0)?,
+                    None => self.chip8.get_program_counter().saturating_sub(8),
+                };
+                self.print_listing(address, 12);
+            }
+            "press" | "release" => {
+                let key = args
+                    .first()
+                    .and_then(|key| u8::from_str_radix(key, 16).ok())
+                    .filter(|&key| (key as usize) < constants::NUM_KEYS)
+                    .ok_or_else(|| format!("Usage: {} <key>, key 0 - F", command))?;
+                if command == "press" {
+                    self.keys |= 1 << key;
+                } else {
+                    self.keys &= !(1 << key);
+                }
+                self.keypad.set_keys(self.keys);
+            }
+            "screen" => print!("{}", headless::screen_text(&self.chip8)),
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(true),
+            _ => return Err(format!("Unknown command `{}`, type `help`", command)),
+        }
+        Ok(false)
+    }
+
+    // Executes one instruction, ticking the timers at the start of every frame as in
+    // `Chip8::run_frame`
+    fn step(&mut self) -> Result<(), Chip8Error> {
+        if self
+            .cycles
+            .is_multiple_of(self.chip8.get_cycles_per_frame() as u64)
+        {
+            self.chip8.tick_timers();
+        }
+        self.cycles += 1;
+        self.chip8.step(&mut self.keypad)
+    }
+
+    // Executes up to `count` instructions, stopping early once `done` holds, on
+    // breakpoints, errors, or when the program waits for a key or exits
+    fn run_until(&mut self, count: u64, done: impl Fn(&chip8::Chip8) -> bool) -> Stop {
+        // Conditions made true by commands since the last run do not stop it
+        for watch in self.watches.values_mut() {
+            if let Watch::Condition { condition, last } = watch {
+                *last = condition.expr.is_true(&self.chip8);
+            }
+        }
+
+        for executed in 0..count {
+            if self.chip8.has_exited() {
+                return Stop::Exited;
+            }
+            let pc = self.chip8.get_program_counter();
+            if executed > 0 {
+                if let Some(condition) = self.breakpoints.get(&pc) {
+                    if condition
+                        .as_ref()
+                        .is_none_or(|condition| condition.expr.is_true(&self.chip8))
+                    {
+                        return Stop::Breakpoint(pc);
+                    }
+                }
+            }
+            if let Err(err) = self.step() {
+                return Stop::Error(err);
+            }
+            if let Some(stop) = self.check_watches(pc) {
+                return stop;
+            }
+            if self.chip8.is_waiting_for_key() {
+                return Stop::WaitingForKey;
+            }
+            if done(&self.chip8) {
+                return Stop::Done;
+            }
+        }
+        Stop::Limit
+    }
+
+    // First watchpoint or condition triggered by the instruction at `pc`, every condition
+    // is evaluated to track when it becomes true
+    fn check_watches(&mut self, pc: u16) -> Option<Stop> {
+        let mut stop = None;
+        for (&number, watch) in self.watches.iter_mut() {
+            let triggered = match watch {
+                Watch::Memory { range, read, write } => self
+                    .chip8
+                    .get_memory_accesses()
+                    .iter()
+                    .find(|access| {
+                        range.contains(&access.address) && if access.write { *write } else { *read }
+                    })
+                    .map(|&access| Stop::Watchpoint { number, access, pc }),
+                Watch::Condition { condition, last } => {
+                    let value = condition.expr.is_true(&self.chip8);
+                    let became_true = value && !*last;
+                    *last = value;
+                    became_true.then_some(Stop::Condition(number))
+                }
+            };
+            if stop.is_none() {
+                stop = triggered;
+            }
+        }
+        stop
+    }
+
+    fn add_watch(&mut self, watch: Watch) -> u32 {
+        let number = self.next_watch;
+        self.watches.insert(number, watch);
+        self.next_watch += 1;
+        number
+    }
+
+    fn condition(&self, args: &[&str]) -> Result<Condition, String> {
+        let text = args.join(" ");
+        if text.is_empty() {
+            return Err("Missing condition".to_string());
+        }
+        let expr = Expr::parse(&text, &self.symbols)?;
+        Ok(Condition { expr, text })
+    }
+
+    // Executes one instruction, or a whole subroutine for 2NNN
+    fn step_over(&mut self) -> Stop {
+        if let Some(Opcode::Call(_)) = self.opcode_at(self.chip8.get_program_counter()) {
+            let depth = self.chip8.get_stack().len();
+            self.run_until(RUN_LIMIT, |chip8| chip8.get_stack().len() <= depth)
+        } else {
+            self.run_until(1, |_| true)
+        }
+    }
+
+    fn report(&self, stop: Stop) {
+        match stop {
+            Stop::Done => {}
+            Stop::Breakpoint(address) => println!("Breakpoint at {}", self.describe(address)),
+            Stop::Watchpoint { number, access, pc } => println!(
+                "Watchpoint {}: {} 0x{:03X} at {}",
+                number,
+                if access.write { "write to" } else { "read of" },
+                access.address,
+                self.describe(pc)
+            ),
+            Stop::Condition(number) => {
+                if let Some(Watch::Condition { condition, .. }) = self.watches.get(&number) {
+                    println!("Condition {}: {}", number, condition.text);
+                }
+            }
+            Stop::WaitingForKey => println!("Waiting for a key, use `press` and `release`"),
+            Stop::Exited => println!("Program exited"),
+            Stop::Limit => println!("Stopped after {} instructions", RUN_LIMIT),
+            Stop::Error(err) => println!("Error: {}", err),
+        }
+        self.print_location();
+    }
+
+    // Instruction at the program counter
+    fn print_location(&self) {
+        let pc = self.chip8.get_program_counter();
+        if let Some(label) = self.labels.get(&pc) {
+            println!("{}:", label);
+        }
+        println!("=> {}", self.instruction_line(pc));
+    }
+
+    fn print_registers(&self) {
+        for (row, registers) in self.chip8.get_registers().chunks(8).enumerate() {
+            let line: Vec<String> = registers
+                .iter()
+                .enumerate()
+                .map(|(index, value)| format!("V{:X}=0x{:02X}", row * 8 + index, value))
+                .collect();
+            println!("{}", line.join(" "));
+        }
+        println!(
+            "I=0x{:03X} PC=0x{:03X} DT=0x{:02X} ST=0x{:02X}",
+            self.chip8.get_index_register(),
+            self.chip8.get_program_counter(),
+            self.chip8.get_delay_timer(),
+            self.chip8.get_sound_timer()
+        );
+        println!("Stack: {:03X?}", self.chip8.get_stack());
+        println!("Cycles: {}", self.cycles);
+    }
+
+    fn print_memory(&self, range: Range<usize>) -> Result<(), String> {
+        let memory = self.chip8.get_memory();
+        if range.start >= memory.len() {
+            return Err(format!("Address 0x{:03X} out of memory", range.start));
+        }
+        for (line, bytes) in memory[range.clone()].chunks(16).enumerate() {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            println!("{:03X}: {}", range.start + line * 16, bytes.join(" "));
+        }
+        Ok(())
+    }
+
+    // Disassembly of `count` instructions from the given address, marking the program
+    // counter with `=>` and breakpoints with `*`
+    fn print_listing(&self, address: u16, count: usize) {
+        let pc = self.chip8.get_program_counter();
+        let mut address = address;
+        for _ in 0..count {
+            if address as usize + 1 >= self.chip8.get_memory().len() {
+                break;
+            }
+            if let Some(label) = self.labels.get(&address) {
+                println!("{}:", label);
+            }
+            let marker = match (address == pc, self.breakpoints.contains_key(&address)) {
+                (true, _) => "=>",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            println!("{} {}", marker, self.instruction_line(address));
+            let size = self.opcode_at(address).map_or(2, |opcode| opcode.size());
+            address = address.wrapping_add(size as u16);
+        }
+    }
+
+    fn instruction_line(&self, address: u16) -> String {
+        let memory = self.chip8.get_memory();
+        if address as usize + 1 >= memory.len() {
+            return format!("0x{:03X}  out of memory", address);
+        }
+        let word = read_word(memory, address as usize);
+        let text = match decode(word) {
+            Ok(Opcode::LdILong) if address as usize + 3 < memory.len() => format!(
+                "LD I, LONG 0x{:04X}",
+                read_word(memory, address as usize + 2)
+            ),
+            Ok(opcode) => opcode.to_string(),
+            Err(_) => "unknown".to_string(),
+        };
+        format!("0x{:03X}  {:04X}  {}", address, word, text)
+    }
+
+    fn opcode_at(&self, address: u16) -> Option<Opcode> {
+        let memory = self.chip8.get_memory();
+        if address as usize + 1 >= memory.len() {
+            return None;
+        }
+        decode(read_word(memory, address as usize)).ok()
+    }
+
+    // Address with its label, if any
+    fn describe(&self, address: u16) -> String {
+        match self.labels.get(&address) {
+            Some(label) => format!("0x{:03X} ({})", address, label),
+            None => format!("0x{:03X}", address),
+        }
+    }
+
+    fn set(&mut self, args: &[&str]) -> Result<(), String> {
+        let (target, value) = match args {
+            [target, value] => (target.to_uppercase(), self.value(value)?),
+            _ => return Err("Usage: set <reg> <value>".to_string()),
+        };
+        let byte = || u8::try_from(value).map_err(|_| format!("Value {} out of range", value));
+        let word = || u16::try_from(value).map_err(|_| format!("Value {} out of range", value));
+        match target.as_str() {
+            "I" => self.chip8.set_index_register(word()?),
+            "PC" => self.chip8.set_program_counter(word()?),
+            "DT" => self.chip8.set_delay_timer(byte()?),
+            "ST" => self.chip8.set_sound_timer(byte()?),
+            _ => {
+                let register = target
+                    .strip_prefix('V')
+                    .filter(|register| register.len() == 1)
+                    .and_then(|register| usize::from_str_radix(register, 16).ok())
+                    .ok_or_else(|| format!("Unknown register `{}`", target))?;
+                self.chip8.set_register(register, byte()?);
+            }
+        }
+        Ok(())
+    }
+
+    fn count_arg(&self, args: &[&str]) -> Result<u64, String> {
+        match args.first() {
+            Some(count) => self.value(count),
+            None => Ok(1),
+        }
+    }
+
+    fn address_arg(&self, args: &[&str], index: usize) -> Result<u16, String> {
+        let arg = args
+            .get(index)
+            .ok_or_else(|| "Missing address".to_string())?;
+        let address = self.value(arg)?;
+        u16::try_from(address).map_err(|_| format!("Address {} out of range", arg))
+    }
+
+    // `length` bytes from `address`, rejected when they don't all fit in memory
+    fn memory_range(&self, address: u16, length: u64) -> Result<Range<usize>, String> {
+        let start = address as usize;
+        usize::try_from(length)
+            .ok()
+            .and_then(|length| start.checked_add(length))
+            .filter(|&end| end <= self.chip8.get_memory().len())
+            .map(|end| start..end)
+            .ok_or_else(|| {
+                format!(
+                    "Range of {} bytes at 0x{:03X} goes past the end of memory",
+                    length, address
+                )
+            })
+    }
+
+    fn byte(&self, arg: &str) -> Result<u8, String> {
+        let value = self.value(arg)?;
+        u8::try_from(value).map_err(|_| format!("Byte {} out of range", arg))
+    }
+
+    // Number, label or expression without spaces, e.g. `sprites+8` or `I`
+    fn value(&self, arg: &str) -> Result<u64, String> {
+        let value = Expr::parse(arg, &self.symbols)?.eval(&self.chip8);
+        u64::try_from(value).map_err(|_| format!("Negative value `{}`", arg))
+    }
+}
+
+fn read_word(memory: &[u8], address: usize) -> u16 {
+    (memory[address] as u16) << 8 | memory[address + 1] as u16
+}
+
+fn access_name(read: bool, write: bool) -> &'static str {
+    match (read, write) {
+        (true, true) => "access",
+        (true, false) => "read",
+        _ => "write",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger() -> Debugger {
+        let rom = format!("{}/roms/ibm_logo.ch8", env!("CARGO_MANIFEST_DIR"));
+        Debugger::new(&rom, 8, constants::STACK_LEVELS, Quirks::default(), false).unwrap()
+    }
+
+    #[test]
+    fn accepts_ranges_up_to_the_end_of_memory() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.execute("watch 0xFF0 16"), Ok(false));
+        assert_eq!(debugger.execute("x 0xFF0 16"), Ok(false));
+        assert_eq!(debugger.execute("x 0xFFF"), Ok(false));
+        assert_eq!(debugger.execute("poke 0xFFE 1 2"), Ok(false));
+        assert_eq!(debugger.chip8.get_memory()[0xFFE..], [1, 2]);
+    }
+
+    #[test]
+    fn rejects_ranges_past_the_end_of_memory() {
+        let mut debugger = debugger();
+        for command in [
+            "watch 0xFF0 17",
+            "rwatch 0xFFF 0xFFFFFFFFFFFFFFFF",
+            "x 0xFFF 2",
+            "x 0x100 0xFFFFFFFFFFFFFFFF",
+            "x 0x1000",
+            "poke 0xFFF 1 2",
+        ] {
+            assert!(debugger.execute(command).is_err(), "{}", command);
+        }
+        assert!(debugger.watches.is_empty());
+        assert_eq!(debugger.chip8.get_memory()[0xFFF], 0);
+    }
+}
@@ -1,18 +1,56 @@
 use std::cell::RefCell;
+use std::fs;
 use std::rc::Rc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::{chip8, media};
+use crate::gdb::GdbStub;
+use crate::{keys, media};
+use chip8_core::error::Chip8Error;
+use chip8_core::keypad::Keypad;
+use chip8_core::movie::{self, Movie, MovieKeypad};
+use chip8_core::quirks::Quirks;
+use chip8_core::rewind::RewindBuffer;
+use chip8_core::rng::Rng;
+use chip8_core::trace::Tracer;
+use chip8_core::{chip8, state};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
+const STATE_SLOTS: u8 = 10;
+// Frames kept for rewinding, 10 seconds
+const REWIND_FRAMES: usize = 600;
+
 pub struct Emulator {
     // Sdl Screen
     sdl_screen: Rc<RefCell<media::Screen>>,
     // Sdl Beep
     sdl_beep: media::Beep,
+    // Sdl Keypad
+    sdl_keypad: keys::SdlKeypad,
     // Chip 8
     chip8: chip8::Chip8,
+    // ROM path, save state slots are stored next to it
+    rom_path: String,
+    // Selected save state slot
+    state_slot: u8,
+    // Snapshots of the recent frames
+    rewind: RewindBuffer,
+    // Rewind hotkey held down
+    rewinding: bool,
+    // Movie being recorded or played back
+    movie: Option<MovieSession>,
+    // Keypad replaying the frame keys of the movie
+    movie_keypad: MovieKeypad,
+    // GDB remote debugging server
+    gdb: Option<GdbStub>,
+    // Settings a movie is recorded with
+    quirks: Quirks,
+}
+
+enum MovieSession {
+    Recording { movie: Movie, path: String },
+    Playing { movie: Movie, frame: usize },
 }
 
 impl Emulator {
@@ -20,31 +58,100 @@ impl Emulator {
         title: &'static str,
         rom_path: &str,
         scale: u32,
-        emulation_instr_second: u128,
+        cycles_per_frame: u32,
+        stack_depth: usize,
+        quirks: Quirks,
         debug: bool,
-    ) -> Self {
+    ) -> Result<Self, Chip8Error> {
         // Sdl Context
         let sdl_context = sdl2::init().unwrap();
         // Sdl Screen
         let sdl_screen = media::Screen::new(&sdl_context, title, scale);
         // Sdl Beep
         let sdl_beep = media::Beep::new(&sdl_context);
+        // Sdl Keypad
+        let sdl_keypad = keys::SdlKeypad::new();
         // Chip8
-        let chip8 = chip8::Chip8::new(rom_path, emulation_instr_second, Self::nanos_time(), debug);
+        let chip8 = chip8::Chip8::new(rom_path, cycles_per_frame, stack_depth, quirks, debug)?;
 
-        Emulator {
+        Ok(Emulator {
             sdl_screen: Rc::new(RefCell::new(sdl_screen)),
             sdl_beep,
+            sdl_keypad,
             chip8,
-        }
+            rom_path: rom_path.to_string(),
+            state_slot: 0,
+            rewind: RewindBuffer::new(REWIND_FRAMES),
+            rewinding: false,
+            movie: None,
+            movie_keypad: MovieKeypad::new(),
+            gdb: None,
+            quirks,
+        })
+    }
+
+    // Records the keys of every frame, written to the given file when the emulator stops
+    pub fn record_movie(&mut self, movie_path: &str) -> Result<(), Chip8Error> {
+        let movie = Movie::new(
+            self.rom_hash()?,
+            self.quirks,
+            // The depth the machine runs with, after clamping
+            self.chip8.get_stack_depth(),
+            self.chip8.get_cycles_per_frame(),
+            self.chip8.uses_vip_rng(),
+            self.chip8.get_seed(),
+        );
+        self.movie = Some(MovieSession::Recording {
+            movie,
+            path: movie_path.to_string(),
+        });
+        Ok(())
+    }
+
+    // Replays the movie stored in the given file, then hands control back to the keyboard
+    pub fn play_movie(&mut self, movie_path: &str) -> Result<(), Chip8Error> {
+        let movie = Movie::read(movie_path)?;
+        movie.check_matches(
+            self.rom_hash()?,
+            self.quirks,
+            self.chip8.get_stack_depth(),
+            self.chip8.get_cycles_per_frame(),
+            self.chip8.uses_vip_rng(),
+        )?;
+        self.chip8.set_seed(movie.seed);
+        self.movie = Some(MovieSession::Playing { movie, frame: 0 });
+        Ok(())
+    }
+
+    // Hands the execution over to an attached GDB client
+    pub fn attach_gdb(&mut self, gdb: GdbStub) {
+        self.gdb = Some(gdb);
+    }
+
+    // Boots into the snapshot stored in the given file
+    pub fn load_state_file(&mut self, state_path: &str) -> Result<(), Chip8Error> {
+        let state = state::read_state_file(state_path)?;
+        self.chip8.load_state(&state)
     }
 
-    pub fn emulate(&mut self) {
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.chip8.set_rng(rng);
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.chip8.set_tracer(tracer);
+    }
+
+    // Runs one frame every 1/60 s: input, emulation, presentation, then sleeps until the
+    // next frame is due
+    pub fn emulate(&mut self) -> Result<(), Chip8Error> {
+        let frame_duration = Duration::from_nanos(crate::FRAME_NANOS);
+        let mut next_frame = Instant::now();
         'mainloop: loop {
-            let current_time = Self::nanos_time();
             let screen = Rc::clone(&self.sdl_screen);
             let screen_ref = screen.as_ref();
 
+            let mut hotkeys = Vec::new();
             for event in screen_ref.borrow().event_pump.borrow_mut().poll_iter() {
                 match event {
                     Event::Quit { .. }
@@ -52,37 +159,156 @@ impl Emulator {
                         keycode: Some(Keycode::Escape),
                         ..
                     } => break 'mainloop,
-                    _ => {}
+                    Event::KeyDown {
+                        keycode:
+                            Some(keycode @ (Keycode::F5 | Keycode::F6 | Keycode::F7 | Keycode::F9)),
+                        repeat: false,
+                        ..
+                    } => hotkeys.push(keycode),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => self.rewinding = true,
+                    Event::KeyUp {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => self.rewinding = false,
+                    _ => self.sdl_keypad.handle_event(&event),
                 }
             }
 
-            // Run a cycle
-            self.chip8
-                .cycle(current_time, screen_ref.borrow().event_pump.borrow_mut());
+            // Play movies, let GDB drive the execution, step one frame backwards while
+            // rewinding, otherwise run a frame and record a snapshot of it
+            if self.movie.is_some() {
+                self.run_movie_frame()?;
+            } else if let Some(gdb) = self.gdb.as_mut() {
+                gdb.run_frame(&mut self.chip8, &mut self.sdl_keypad);
+            } else if self.rewinding {
+                if let Some(state) = self.rewind.pop() {
+                    self.chip8.load_state(&state)?;
+                }
+            } else {
+                self.chip8.run_frame(&mut self.sdl_keypad)?;
+                self.rewind.push(self.chip8.save_state());
+            }
 
-            // Change display
+            // Save states, unavailable during movies as they would break the recording
+            if self.movie.is_none() {
+                for hotkey in hotkeys {
+                    self.handle_state_hotkey(hotkey);
+                }
+            }
+
+            // Present the frame
             if self.chip8.should_clear_screen() {
                 screen_ref.borrow_mut().clear_screen();
             }
             if self.chip8.should_update_screen() {
-                screen_ref
-                    .borrow_mut()
-                    .update_screen(self.chip8.get_screen());
+                screen_ref.borrow_mut().update_screen(
+                    self.chip8.get_screen(),
+                    self.chip8.get_screen_width(),
+                    self.chip8.get_screen_height(),
+                );
             }
 
             // Audio media
-            if self.chip8.should_beep() {
+            self.sdl_beep.set_pattern(
+                self.chip8.get_audio_pattern(),
+                self.chip8.get_pattern_rate(),
+            );
+            if self.chip8.should_beep() && (!self.rewinding || self.movie.is_some()) {
                 self.sdl_beep.play();
             } else {
                 self.sdl_beep.pause();
             }
+
+            // Program stopped itself with 00FD
+            if self.chip8.has_exited() {
+                break 'mainloop;
+            }
+
+            // Wait for the next frame, skipping the missed ones when running late
+            next_frame += frame_duration;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+
+        if let Some(MovieSession::Recording { movie, path }) = &self.movie {
+            movie.write(path)?;
+            println!("Recorded {} frames to {}", movie.frames.len(), path);
+        }
+        Ok(())
+    }
+
+    // Runs one frame with the keys recorded for it, live key events only count as the
+    // keys held at the start of the frame
+    fn run_movie_frame(&mut self) -> Result<(), Chip8Error> {
+        let keys = match &mut self.movie {
+            Some(MovieSession::Recording { movie, .. }) => {
+                let keys = self.sdl_keypad.pressed_keys();
+                movie.frames.push(keys);
+                Some(keys)
+            }
+            Some(MovieSession::Playing { movie, frame }) => {
+                let keys = movie.frames.get(*frame).copied();
+                *frame += 1;
+                keys
+            }
+            None => None,
+        };
+
+        match keys {
+            Some(keys) => {
+                while self.sdl_keypad.next_event().is_some() {}
+                self.movie_keypad.set_keys(keys);
+                self.chip8.run_frame(&mut self.movie_keypad)
+            }
+            None => {
+                println!("Movie playback finished");
+                self.movie = None;
+                self.chip8.run_frame(&mut self.sdl_keypad)
+            }
         }
     }
 
-    fn nanos_time() -> u128 {
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos()
+    fn rom_hash(&self) -> Result<u64, Chip8Error> {
+        fs::read(&self.rom_path)
+            .map(|rom| movie::rom_hash(&rom))
+            .map_err(|_| Chip8Error::RomNotFound(self.rom_path.clone()))
+    }
+
+    // F5 saves to the selected slot, F9 loads from it, F6/F7 select the previous/next slot
+    fn handle_state_hotkey(&mut self, hotkey: Keycode) {
+        let state_path = format!("{}.state{}", self.rom_path, self.state_slot);
+        let result = match hotkey {
+            Keycode::F5 => {
+                let state = self.chip8.save_state();
+                state::write_state_file(&state_path, &state)
+                    .map(|_| println!("Saved state to {}", state_path))
+            }
+            Keycode::F9 => state::read_state_file(&state_path)
+                .and_then(|state| self.chip8.load_state(&state))
+                .map(|_| {
+                    self.rewind.clear();
+                    println!("Loaded state from {}", state_path)
+                }),
+            Keycode::F6 | Keycode::F7 => {
+                self.state_slot = if hotkey == Keycode::F6 {
+                    (self.state_slot + STATE_SLOTS - 1) % STATE_SLOTS
+                } else {
+                    (self.state_slot + 1) % STATE_SLOTS
+                };
+                println!("Selected state slot {}", self.state_slot);
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
     }
 }
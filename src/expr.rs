@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+
+use chip8_core::asm;
+use chip8_core::chip8::Chip8;
+
+// Debugger condition over the machine state, e.g. `V3 == 0x10 && I > 0x300`
+// Operands are numbers, labels, V0-VF, I, PC, DT, ST and memory bytes `[addr]`
+// Comparisons and logical operators evaluate to 1 or 0, any non-zero value is true
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Value(i64),
+    Register(usize),
+    Index,
+    ProgramCounter,
+    DelayTimer,
+    SoundTimer,
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+// Binary operators from the loosest to the tightest binding
+const PRECEDENCE: [&[(&str, BinaryOp)]; 7] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+];
+
+impl Expr {
+    // Parses an expression, labels are resolved through the upper case symbol names
+    pub fn parse(text: &str, symbols: &BTreeMap<String, u16>) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            symbols,
+        };
+        let expr = parser.binary(0)?;
+        match parser.tokens.get(parser.position) {
+            Some(token) => Err(format!("Unexpected `{}` in expression", token)),
+            None => Ok(expr),
+        }
+    }
+
+    pub fn eval(&self, chip8: &Chip8) -> i64 {
+        match self {
+            Expr::Value(value) => *value,
+            Expr::Register(register) => chip8.get_registers()[*register] as i64,
+            Expr::Index => chip8.get_index_register() as i64,
+            Expr::ProgramCounter => chip8.get_program_counter() as i64,
+            Expr::DelayTimer => chip8.get_delay_timer() as i64,
+            Expr::SoundTimer => chip8.get_sound_timer() as i64,
+            Expr::Memory(address) => usize::try_from(address.eval(chip8))
+                .ok()
+                .and_then(|address| chip8.get_memory().get(address))
+                .map_or(0, |&byte| byte as i64),
+            Expr::Not(expr) => (expr.eval(chip8) == 0) as i64,
+            Expr::Negate(expr) => expr.eval(chip8).wrapping_neg(),
+            Expr::Binary(op, left, right) => {
+                let left = left.eval(chip8);
+                // Logical operators short-circuit
+                match op {
+                    BinaryOp::Or if left != 0 => return 1,
+                    BinaryOp::And if left == 0 => return 0,
+                    _ => {}
+                }
+                let right = right.eval(chip8);
+                match op {
+                    BinaryOp::Or | BinaryOp::And => (right != 0) as i64,
+                    BinaryOp::Eq => (left == right) as i64,
+                    BinaryOp::Ne => (left != right) as i64,
+                    BinaryOp::Lt => (left < right) as i64,
+                    BinaryOp::Le => (left <= right) as i64,
+                    BinaryOp::Gt => (left > right) as i64,
+                    BinaryOp::Ge => (left >= right) as i64,
+                    BinaryOp::BitOr => left | right,
+                    BinaryOp::BitXor => left ^ right,
+                    BinaryOp::BitAnd => left & right,
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, chip8: &Chip8) -> bool {
+        self.eval(chip8) != 0
+    }
+}
+
+// Words are numbers, registers or labels, everything else is an operator
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    const OPERATORS: [&str; 17] = [
+        "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "(", ")", "[",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let word_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(rest.len());
+        let len = if word_len > 0 {
+            word_len
+        } else if rest.starts_with(']') {
+            1
+        } else {
+            OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+                .map(|operator| operator.len())
+                .ok_or_else(|| {
+                    let unexpected = rest.chars().next().unwrap_or_default();
+                    format!("Unexpected `{}` in expression", unexpected)
+                })?
+        };
+        tokens.push(rest[..len].to_string());
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// Recursive descent parser, one level per entry of `PRECEDENCE`
+struct Parser<'a> {
+    tokens: Vec<String>,
+    position: usize,
+    symbols: &'a BTreeMap<String, u16>,
+}
+
+impl Parser<'_> {
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut expr = self.binary(level + 1)?;
+        while let Some(&(_, op)) = self
+            .peek()
+            .and_then(|token| PRECEDENCE[level].iter().find(|(name, _)| *name == token))
+        {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = self
+            .next()
+            .ok_or_else(|| "Unexpected end of expression".to_string())?;
+        match token.as_str() {
+            "!" => Ok(Expr::Not(Box::new(self.unary()?))),
+            "-" => Ok(Expr::Negate(Box::new(self.unary()?))),
+            "(" => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            "[" => {
+                let address = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(address)))
+            }
+            _ => self.operand(&token),
+        }
+    }
+
+    fn operand(&self, token: &str) -> Result<Expr, String> {
+        let upper = token.to_uppercase();
+        let register = upper
+            .strip_prefix('V')
+            .filter(|register| register.len() == 1)
+            .and_then(|register| usize::from_str_radix(register, 16).ok());
+        if let Some(register) = register {
+            return Ok(Expr::Register(register));
+        }
+        match upper.as_str() {
+            "I" => Ok(Expr::Index),
+            "PC" => Ok(Expr::ProgramCounter),
+            "DT" => Ok(Expr::DelayTimer),
+            "ST" => Ok(Expr::SoundTimer),
+            _ => asm::parse_number(&upper)
+                .or_else(|| self.symbols.get(&upper).map(|&address| address as i64))
+                .map(Expr::Value)
+                .ok_or_else(|| format!("Unknown value or label `{}`", token)),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("Expected `{}` in expression", expected)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::constants;
+    use chip8_core::quirks::Quirks;
+
+    fn symbols() -> BTreeMap<String, u16> {
+        BTreeMap::from([("SPRITES".to_string(), 0x300), ("LOOP".to_string(), 0x208)])
+    }
+
+    fn eval(text: &str) -> Result<i64, String> {
+        let mut chip8 = Chip8::from_rom(
+            &[0x12, 0x00],
+            8,
+            constants::STACK_LEVELS,
+            Quirks::default(),
+            0,
+        )
+        .unwrap();
+        chip8.set_register(3, 0x10);
+        chip8.set_register(0xF, 1);
+        chip8.set_index_register(0x302);
+        chip8.set_delay_timer(5);
+        chip8.set_memory(0x302, 0xAB);
+        Ok(Expr::parse(text, &symbols())?.eval(&chip8))
+    }
+
+    #[test]
+    fn reads_the_machine_state() {
+        assert_eq!(eval("V3"), Ok(0x10));
+        assert_eq!(eval("vf"), Ok(1));
+        assert_eq!(eval("I"), Ok(0x302));
+        assert_eq!(eval("PC"), Ok(0x200));
+        assert_eq!(eval("DT + ST"), Ok(5));
+        assert_eq!(eval("[I]"), Ok(0xAB));
+        assert_eq!(eval("[sprites + 2] == 0xAB"), Ok(1));
+        // Outside of memory reads as 0
+        assert_eq!(eval("[-1] + [0x10000]"), Ok(0));
+    }
+
+    #[test]
+    fn resolves_labels_and_numbers() {
+        assert_eq!(eval("loop"), Ok(0x208));
+        assert_eq!(eval("Sprites+8"), Ok(0x308));
+        assert_eq!(eval("0x1F + 0b10 + 10"), Ok(0x1F + 2 + 10));
+    }
+
+    #[test]
+    fn applies_operator_precedence() {
+        assert_eq!(eval("1 + 2 == 3 && 4 > 3"), Ok(1));
+        // 6 | (3 ^ (5 & 6)), 2 from left to right
+        assert_eq!(eval("6 | 3 ^ 5 & 6"), Ok(7));
+        assert_eq!(eval("0 || 0 && 1"), Ok(0));
+        assert_eq!(eval("1 || 0 && 0"), Ok(1));
+        assert_eq!(eval("(1 | 2) == 3"), Ok(1));
+        // Comparisons bind looser than bitwise operators, unlike in C
+        assert_eq!(eval("1 | 2 == 3"), Ok(1));
+        assert_eq!(eval("10 - 3 - 2"), Ok(5));
+        assert_eq!(eval("-V3 + 1"), Ok(-15));
+        assert_eq!(eval("!V3 || !0"), Ok(1));
+        assert_eq!(eval("V3 >= 0x10 && V3 <= 0x10 && V3 != 0"), Ok(1));
+    }
+
+    #[test]
+    fn reports_malformed_expressions() {
+        let error = |text: &str| eval(text).unwrap_err();
+        assert_eq!(error("V3 =="), "Unexpected end of expression");
+        assert_eq!(error("(1 + 2"), "Expected `)` in expression");
+        assert_eq!(error("[I"), "Expected `]` in expression");
+        assert_eq!(error("1 2"), "Unexpected `2` in expression");
+        assert_eq!(error("nowhere"), "Unknown value or label `nowhere`");
+        assert_eq!(error("0xZZ"), "Unknown value or label `0xZZ`");
+        assert_eq!(error("V3 = 1"), "Unexpected `=` in expression");
+        assert_eq!(
+            error("V3 \u{2260} 1"),
+            "Unexpected `\u{2260}` in expression"
+        );
+        assert_eq!(error("é"), "Unexpected `é` in expression");
+    }
+}
@@ -0,0 +1,616 @@
+use std::collections::BTreeSet;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use chip8_core::chip8::Chip8;
+use chip8_core::error::Chip8Error;
+use chip8_core::keypad::Keypad;
+
+// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// Register numbers of the `g`, `G`, `p` and `P` packets, 16 bit registers are big endian
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const NUM_GDB_REGISTERS: usize = 21;
+
+// Input received from GDB
+enum Input {
+    // Ctrl-C, 0x03 sent outside of a packet
+    Interrupt,
+    Packet(String),
+}
+
+// GDB remote serial protocol server on a local TCP port
+// Registers are numbered V0-VF (0-15), I, PC, SP, DT and ST (16-20)
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    // Received bytes not parsed into packets yet
+    input: Vec<u8>,
+    breakpoints: BTreeSet<u16>,
+    // Execution stopped, waiting for GDB to resume it
+    halted: bool,
+    // Resuming from a breakpoint, whose instruction runs before stopping again
+    resuming: bool,
+    // Instructions executed in the current frame
+    frame_cycle: u32,
+}
+
+impl GdbStub {
+    // Waits for GDB to attach to the local port, the program starts halted
+    pub fn listen(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .map_err(|err| format!("Could not listen on port {}: {}", port, err))?;
+        println!("Waiting for GDB to attach on port {}", port);
+        Self::attach(listener)
+    }
+
+    // Waits for GDB to connect to the listener
+    fn attach(listener: TcpListener) -> Result<Self, String> {
+        let (client, _) = listener
+            .accept()
+            .map_err(|err| format!("Could not accept GDB connection: {}", err))?;
+        client
+            .set_nonblocking(true)
+            .and_then(|_| client.set_nodelay(true))
+            .and_then(|_| listener.set_nonblocking(true))
+            .map_err(|err| format!("Could not set up GDB connection: {}", err))?;
+
+        Ok(GdbStub {
+            listener,
+            client: Some(client),
+            input: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            halted: true,
+            resuming: false,
+            frame_cycle: 0,
+        })
+    }
+
+    // Handles the packets received since the last frame, then runs the rest of the
+    // frame unless GDB holds control
+    pub fn run_frame(&mut self, chip8: &mut Chip8, keypad: &mut dyn Keypad) {
+        self.accept();
+        self.receive();
+        while let Some(input) = self.next_input() {
+            match input {
+                Input::Interrupt if !self.halted => self.stop(&signal_reply(SIGINT)),
+                Input::Interrupt => {}
+                Input::Packet(packet) => self.handle(&packet, chip8, keypad),
+            }
+        }
+
+        while !self.halted {
+            let pc = chip8.get_program_counter();
+            if !self.resuming && self.breakpoints.contains(&pc) {
+                self.stop(&signal_reply(SIGTRAP));
+                break;
+            }
+            self.resuming = false;
+            if let Some(reply) = self.step(chip8, keypad) {
+                self.stop(&reply);
+            }
+            if self.frame_cycle == 0 {
+                break;
+            }
+        }
+    }
+
+    // Executes one instruction, starting a new frame every `cycles_per_frame` of them
+    // Returns the stop reply when the program exits or fails
+    fn step(&mut self, chip8: &mut Chip8, keypad: &mut dyn Keypad) -> Option<String> {
+        if self.frame_cycle == 0 {
+            chip8.start_frame();
+        }
+        self.frame_cycle = (self.frame_cycle + 1) % chip8.get_cycles_per_frame();
+        match chip8.step(keypad) {
+            Ok(_) if chip8.has_exited() => Some("W00".to_string()),
+            Ok(_) => None,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                match err {
+                    Chip8Error::UnknownOpcode { .. } => Some(signal_reply(SIGILL)),
+                    _ => Some(signal_reply(SIGSEGV)),
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self, reply: &str) {
+        self.halted = true;
+        self.send_packet(reply);
+    }
+
+    fn handle(&mut self, packet: &str, chip8: &mut Chip8, keypad: &mut dyn Keypad) {
+        let (command, args) = match packet.char_indices().nth(1) {
+            Some((index, _)) => packet.split_at(index),
+            None => (packet, ""),
+        };
+        let reply = match command {
+            "?" => signal_reply(SIGTRAP),
+            "g" => (0..NUM_GDB_REGISTERS)
+                .map(|register| to_hex(&read_register(chip8, register)))
+                .collect(),
+            // Every register is checked before any is written
+            "G" => {
+                let values = from_hex(args).and_then(|bytes| split_registers(&bytes));
+                let ok = values.is_some_and(|values| {
+                    let valid = values.iter().enumerate().all(|(register, value)| {
+                        register != REG_SP || value[0] as usize <= chip8.get_stack_depth()
+                    });
+                    if valid {
+                        for (register, value) in values.iter().enumerate() {
+                            write_register(chip8, register, value);
+                        }
+                    }
+                    valid
+                });
+                ok_or_error(ok)
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register) if register < NUM_GDB_REGISTERS => {
+                    to_hex(&read_register(chip8, register))
+                }
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let ok = args.split_once('=').is_some_and(|(register, value)| {
+                    match (usize::from_str_radix(register, 16), from_hex(value)) {
+                        (Ok(register), Some(value)) if register < NUM_GDB_REGISTERS => {
+                            write_register(chip8, register, &value)
+                        }
+                        _ => false,
+                    }
+                });
+                ok_or_error(ok)
+            }
+            "m" => match parse_range(args, chip8) {
+                Some((address, length)) => to_hex(&chip8.get_memory()[address..address + length]),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let ok = args.split_once(':').is_some_and(|(range, data)| {
+                    match (parse_range(range, chip8), from_hex(data)) {
+                        (Some((address, length)), Some(bytes)) if bytes.len() == length => {
+                            for (offset, &byte) in bytes.iter().enumerate() {
+                                chip8.set_memory(address + offset, byte);
+                            }
+                            true
+                        }
+                        _ => false,
+                    }
+                });
+                ok_or_error(ok)
+            }
+            // Software and hardware breakpoints behave the same, watchpoints are unsupported
+            "Z" | "z" => {
+                let mut parts = args.split(',');
+                let kind = parts.next();
+                let address = parts.next().and_then(parse_hex);
+                match (kind, address) {
+                    (Some("0" | "1"), Some(address)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(address as u16);
+                        } else {
+                            self.breakpoints.remove(&(address as u16));
+                        }
+                        "OK".to_string()
+                    }
+                    _ => String::new(),
+                }
+            }
+            "c" | "s" => {
+                if let Some(address) = parse_hex(args) {
+                    chip8.set_program_counter(address as u16);
+                }
+                if command == "c" {
+                    self.halted = false;
+                    self.resuming = true;
+                    return;
+                }
+                self.step(chip8, keypad)
+                    .unwrap_or_else(|| signal_reply(SIGTRAP))
+            }
+            "D" => {
+                self.send_packet("OK");
+                self.detach();
+                return;
+            }
+            "k" => {
+                self.detach();
+                return;
+            }
+            "H" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => {
+                "PacketSize=1000;qXfer:features:read+".to_string()
+            }
+            "q" if args.starts_with("Xfer:features:read:") => {
+                read_target_description(&args["Xfer:features:read:".len()..])
+            }
+            "q" if args == "Attached" => "1".to_string(),
+            _ => String::new(),
+        };
+        self.send_packet(&reply);
+    }
+
+    // Picks up a new connection after GDB detached
+    fn accept(&mut self) {
+        if self.client.is_some() {
+            return;
+        }
+        if let Ok((client, _)) = self.listener.accept() {
+            if client.set_nonblocking(true).is_ok() {
+                client.set_nodelay(true).unwrap_or_default();
+                self.client = Some(client);
+                self.input.clear();
+                self.halted = true;
+            }
+        }
+    }
+
+    // Lets the program run freely until GDB attaches again
+    fn detach(&mut self) {
+        self.client = None;
+        self.breakpoints.clear();
+        self.halted = false;
+        self.resuming = true;
+    }
+
+    fn receive(&mut self) {
+        let mut buffer = [0; 1024];
+        while let Some(client) = self.client.as_mut() {
+            match client.read(&mut buffer) {
+                Ok(0) => self.detach(),
+                Ok(len) => self.input.extend_from_slice(&buffer[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => self.detach(),
+            }
+        }
+    }
+
+    // Next interrupt or packet with a valid checksum, acknowledging packets as they
+    // are parsed
+    fn next_input(&mut self) -> Option<Input> {
+        loop {
+            match *self.input.first()? {
+                0x03 => {
+                    self.input.remove(0);
+                    return Some(Input::Interrupt);
+                }
+                b'$' => {
+                    let end = self.input.iter().position(|&byte| byte == b'#')?;
+                    if self.input.len() < end + 3 {
+                        return None;
+                    }
+                    let data = self.input[1..end].to_vec();
+                    let checksum = std::str::from_utf8(&self.input[end + 1..end + 3])
+                        .ok()
+                        .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+                    self.input.drain(..end + 3);
+                    if checksum == Some(checksum_of(&data)) {
+                        self.send(b"+");
+                        return Some(Input::Packet(String::from_utf8_lossy(&data).into_owned()));
+                    }
+                    self.send(b"-");
+                }
+                // Acknowledgements of our packets
+                _ => {
+                    self.input.remove(0);
+                }
+            }
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.send(packet.as_bytes());
+    }
+
+    // Writes to the non-blocking socket, waiting for room in its buffer
+    fn send(&mut self, mut bytes: &[u8]) {
+        while let Some(client) = self.client.as_mut() {
+            if bytes.is_empty() {
+                break;
+            }
+            match client.write(bytes) {
+                Ok(0) => self.detach(),
+                Ok(len) => bytes = &bytes[len..],
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(1))
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => self.detach(),
+            }
+        }
+    }
+}
+
+// Splits the bytes of a `G` packet into the value of every register
+fn split_registers(mut bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut values = Vec::with_capacity(NUM_GDB_REGISTERS);
+    for register in 0..NUM_GDB_REGISTERS {
+        let size = register_size(register);
+        if bytes.len() < size {
+            return None;
+        }
+        values.push(bytes[..size].to_vec());
+        bytes = &bytes[size..];
+    }
+    bytes.is_empty().then_some(values)
+}
+
+// Target description naming the registers in the order of the `g` packet, so clients
+// show V0-VF, I, PC, SP, DT and ST with their sizes
+fn target_description() -> String {
+    let registers: String = (0..NUM_GDB_REGISTERS)
+        .map(|register| {
+            let (name, kind) = match register {
+                REG_I => ("i".to_string(), "data_ptr"),
+                REG_PC => ("pc".to_string(), "code_ptr"),
+                REG_SP => ("sp".to_string(), "uint8"),
+                REG_DT => ("dt".to_string(), "uint8"),
+                REG_ST => ("st".to_string(), "uint8"),
+                _ => (format!("v{:x}", register), "uint8"),
+            };
+            format!(
+                "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>",
+                name,
+                register_size(register) * 8,
+                kind,
+                register
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">{}</feature></target>",
+        registers
+    )
+}
+
+// Reply to `qXfer:features:read:target.xml:offset,length`, `m` followed by a part of the
+// description, or `l` with its last part
+// The description has none of the characters the protocol would need escaped
+fn read_target_description(args: &str) -> String {
+    let range = args
+        .strip_prefix("target.xml:")
+        .and_then(|range| range.split_once(','))
+        .and_then(|(offset, length)| Some((parse_hex(offset)?, parse_hex(length)?)));
+    match range {
+        Some((offset, length)) => {
+            let description = target_description();
+            let start = offset.min(description.len());
+            let end = offset.saturating_add(length).min(description.len());
+            let marker = if end == description.len() { 'l' } else { 'm' };
+            format!("{}{}", marker, &description[start..end])
+        }
+        None => "E00".to_string(),
+    }
+}
+
+fn register_size(register: usize) -> usize {
+    match register {
+        REG_I | REG_PC => 2,
+        _ => 1,
+    }
+}
+
+fn read_register(chip8: &Chip8, register: usize) -> Vec<u8> {
+    match register {
+        REG_I => chip8.get_index_register().to_be_bytes().to_vec(),
+        REG_PC => chip8.get_program_counter().to_be_bytes().to_vec(),
+        REG_SP => vec![chip8.get_stack().len() as u8],
+        REG_DT => vec![chip8.get_delay_timer()],
+        REG_ST => vec![chip8.get_sound_timer()],
+        _ => vec![chip8.get_registers()[register]],
+    }
+}
+
+fn write_register(chip8: &mut Chip8, register: usize, value: &[u8]) -> bool {
+    if value.len() != register_size(register) {
+        return false;
+    }
+    match register {
+        REG_I => chip8.set_index_register(u16::from_be_bytes([value[0], value[1]])),
+        REG_PC => chip8.set_program_counter(u16::from_be_bytes([value[0], value[1]])),
+        REG_SP if value[0] as usize <= chip8.get_stack_depth() => {
+            chip8.set_stack_pointer(value[0] as usize)
+        }
+        REG_SP => return false,
+        REG_DT => chip8.set_delay_timer(value[0]),
+        REG_ST => chip8.set_sound_timer(value[0]),
+        _ => chip8.set_register(register, value[0]),
+    }
+    true
+}
+
+// `addr,length` in hex, checked against the memory size
+fn parse_range(range: &str, chip8: &Chip8) -> Option<(usize, usize)> {
+    let (address, length) = range.split_once(',')?;
+    let (address, length) = (parse_hex(address)?, parse_hex(length)?);
+    let end = address.checked_add(length)?;
+    (end <= chip8.get_memory().len()).then_some((address, length))
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn signal_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn ok_or_error(ok: bool) -> String {
+    if ok { "OK" } else { "E01" }.to_string()
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::constants;
+    use chip8_core::movie::MovieKeypad;
+    use chip8_core::quirks::Quirks;
+
+    // Client side of the protocol, blocking on every reply
+    struct Client(TcpStream);
+
+    impl Client {
+        fn command(&mut self, data: &str) -> String {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            self.0.write_all(packet.as_bytes()).unwrap();
+            self.reply()
+        }
+
+        // Next packet from the stub, skipping its acknowledgements
+        fn reply(&mut self) -> String {
+            let mut received = Vec::new();
+            let mut byte = [0];
+            loop {
+                self.0.read_exact(&mut byte).unwrap();
+                match byte[0] {
+                    b'+' if received.is_empty() => {}
+                    b'#' => break,
+                    byte => received.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.0.read_exact(&mut checksum).unwrap();
+            let data = &received[1..];
+            assert_eq!(received[0], b'$');
+            assert_eq!(
+                u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16),
+                Ok(checksum_of(data))
+            );
+            self.0.write_all(b"+").unwrap();
+            String::from_utf8(data.to_vec()).unwrap()
+        }
+    }
+
+    // Runs the client against a stub driving the ROM, frame by frame, until it is done
+    fn run_session(rom: &[u8], session: impl FnOnce(&mut Client) + Send + 'static) -> Chip8 {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .and_then(|_| stream.set_nodelay(true))
+                .unwrap();
+            session(&mut Client(stream));
+        });
+        let mut gdb = GdbStub::attach(listener).unwrap();
+        let mut chip8 =
+            Chip8::from_rom(rom, 8, constants::STACK_LEVELS, Quirks::default(), 0).unwrap();
+        let mut keypad = MovieKeypad::new();
+        while !client.is_finished() {
+            gdb.run_frame(&mut chip8, &mut keypad);
+            thread::sleep(Duration::from_millis(1));
+        }
+        client.join().unwrap();
+        chip8
+    }
+
+    // LD V0, 5 / loop: ADD V0, 1 / JP loop
+    const COUNTER: [u8; 6] = [0x60, 0x05, 0x70, 0x01, 0x12, 0x02];
+
+    #[test]
+    fn reads_memory_and_registers_then_stops_at_breakpoints() {
+        let chip8 = run_session(&COUNTER, |client| {
+            assert_eq!(client.command("?"), "S05");
+            assert_eq!(client.command("m200,6"), "600570011202");
+            assert_eq!(client.command("mfff,2"), "E01");
+            assert_eq!(client.command("Z0,204,2"), "OK");
+            // Stops on the breakpoint after LD V0, 5 and ADD V0, 1
+            assert_eq!(client.command("c"), "S05");
+            let registers = client.command("g");
+            assert_eq!(&registers[..2], "06");
+            assert_eq!(&registers[32..40], "00000204");
+            // Steps over the breakpoint onto the ADD
+            assert_eq!(client.command("s"), "S05");
+            assert_eq!(client.command("p11"), "0202");
+            // Comes back to the breakpoint after one more ADD
+            assert_eq!(client.command("c"), "S05");
+            assert_eq!(client.command("p0"), "07");
+            assert_eq!(client.command("z0,204,2"), "OK");
+            assert_eq!(client.command("D"), "OK");
+        });
+        // Detaching lets the program run on
+        assert!(chip8.get_registers()[0] >= 7);
+    }
+
+    #[test]
+    fn writes_every_register_or_none() {
+        run_session(&COUNTER, |client| {
+            let registers = client.command("g");
+            assert_eq!(registers.len(), 2 * 23);
+            // SP past the stack depth is rejected after V0 would have been written
+            let mut invalid = format!("aa{}", &registers[2..]);
+            invalid.replace_range(40..42, "ff");
+            assert_eq!(client.command(&format!("G{}", invalid)), "E01");
+            assert_eq!(client.command(&format!("G{}", &registers[..44])), "E01");
+            assert_eq!(client.command("g"), registers);
+
+            let valid = format!("aa{}", &registers[2..]);
+            assert_eq!(client.command(&format!("G{}", valid)), "OK");
+            assert_eq!(client.command("p0"), "aa");
+            assert_eq!(client.command("D"), "OK");
+        });
+    }
+
+    #[test]
+    fn describes_the_registers() {
+        run_session(&COUNTER, |client| {
+            let supported = client.command("qSupported:multiprocess+;xmlRegisters=i386");
+            assert!(supported.contains("qXfer:features:read+"));
+            let mut description = String::new();
+            loop {
+                let part = client.command(&format!(
+                    "qXfer:features:read:target.xml:{:x},40",
+                    description.len()
+                ));
+                description.push_str(&part[1..]);
+                if part.starts_with('l') {
+                    break;
+                }
+                assert!(part.starts_with('m'));
+            }
+            assert_eq!(description, target_description());
+            assert_eq!(description.matches("<reg ").count(), NUM_GDB_REGISTERS);
+            assert!(
+                description.contains(r#"<reg name="vf" bitsize="8" type="uint8" regnum="15"/>"#)
+            );
+            assert!(description
+                .contains(r#"<reg name="pc" bitsize="16" type="code_ptr" regnum="17"/>"#));
+            assert_eq!(client.command("qXfer:features:read:other.xml:0,40"), "E00");
+            assert_eq!(client.command("D"), "OK");
+        });
+    }
+}
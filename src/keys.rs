@@ -1,33 +1,17 @@
+use std::collections::VecDeque;
+
+use sdl2::event::Event;
 use sdl2::keyboard::Scancode;
 
-// Converts bytes into scan codes
+use chip8_core::constants;
+use chip8_core::keypad::{KeyEvent, Keypad};
+
+// Converts scan codes into bytes
 // The mapping is done with the following keys:
 // 1 2 3 C      1 2 3 4
 // 4 5 6 D      Q W E R
 // 7 8 9 E  =>  A S D F
 // A 0 B F      Z X C V
-pub fn map(code: u8) -> Scancode {
-    match code {
-        0x00 => Scancode::X,
-        0x01 => Scancode::Num1,
-        0x02 => Scancode::Num2,
-        0x03 => Scancode::Num3,
-        0x04 => Scancode::Q,
-        0x05 => Scancode::W,
-        0x06 => Scancode::E,
-        0x07 => Scancode::A,
-        0x08 => Scancode::S,
-        0x09 => Scancode::D,
-        0x0A => Scancode::Z,
-        0x0B => Scancode::C,
-        0x0C => Scancode::Num4,
-        0x0D => Scancode::R,
-        0x0E => Scancode::F,
-        0x0F => Scancode::V,
-        _ => Scancode::Escape,
-    }
-}
-
 pub fn unmap(scancode: Scancode) -> Option<u8> {
     match scancode {
         Scancode::X => Some(0x00),
@@ -49,3 +33,61 @@ pub fn unmap(scancode: Scancode) -> Option<u8> {
         _ => None,
     }
 }
+
+// SDL Keypad, fed with the events polled by the emulator
+pub struct SdlKeypad {
+    pressed: [bool; constants::NUM_KEYS],
+    events: VecDeque<KeyEvent>,
+}
+
+impl SdlKeypad {
+    pub fn new() -> Self {
+        SdlKeypad {
+            pressed: [false; constants::NUM_KEYS],
+            events: VecDeque::new(),
+        }
+    }
+
+    // Records key presses and releases from an SDL event
+    pub fn handle_event(&mut self, event: &Event) {
+        let key_event = match *event {
+            Event::KeyDown {
+                scancode: Some(scancode),
+                repeat: false,
+                ..
+            } => unmap(scancode).map(KeyEvent::Down),
+            Event::KeyUp {
+                scancode: Some(scancode),
+                ..
+            } => unmap(scancode).map(KeyEvent::Up),
+            _ => None,
+        };
+
+        if let Some(key_event) = key_event {
+            match key_event {
+                KeyEvent::Down(key) => self.pressed[key as usize] = true,
+                KeyEvent::Up(key) => self.pressed[key as usize] = false,
+            }
+            self.events.push_back(key_event);
+        }
+    }
+
+    // Keys held down, one bit per key
+    pub fn pressed_keys(&self) -> u16 {
+        self.pressed
+            .iter()
+            .enumerate()
+            .filter(|(_, &pressed)| pressed)
+            .fold(0, |keys, (key, _)| keys | 1 << key)
+    }
+}
+
+impl Keypad for SdlKeypad {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.pressed.get(key as usize).copied().unwrap_or_default()
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+}
@@ -1,27 +1,118 @@
-use clap::{Arg, Command};
+use std::path::{Path, PathBuf};
+use std::{fs, process};
+
+use chip8_core::{asm, constants, disasm, error, headless, opcode, quirks, rng, trace};
+use clap::{Arg, ArgMatches, Command};
+use debugger::Debugger;
+use disasm::Syntax;
 use emulator::Emulator;
+use error::Chip8Error;
+use gdb::GdbStub;
+use headless::{Headless, RunLength, ScriptedKeypad};
+use opcode::OpcodeClass;
+use quirks::Quirks;
+use rand::random;
+use rng::Rng;
+use trace::{TraceFilter, TraceFormat, Tracer};
+use tui::{Glyphs, Tui};
 
-mod chip8;
-mod constants;
+mod debugger;
 mod emulator;
+mod expr;
+mod gdb;
 mod keys;
 mod media;
+mod tui;
+
+const DEF_SCALE: u32 = 10;
+const EMULATION_IPS: u128 = 500;
+const HEADLESS_FRAMES: u64 = 600;
+// Duration of a 60 Hz frame, shared by the SDL and terminal frontends
+const FRAME_NANOS: u64 = 16_666_666;
 
 fn main() {
     let scale_param_help = format!(
         "Positive integer for screen scale, default {} for resolution {} x {}",
-        constants::DEF_SCALE,
-        constants::SCREEN_WIDTH * constants::DEF_SCALE as usize,
-        constants::SCREEN_HEIGHT * constants::DEF_SCALE as usize
+        DEF_SCALE,
+        constants::SCREEN_WIDTH * DEF_SCALE as usize,
+        constants::SCREEN_HEIGHT * DEF_SCALE as usize
     );
     let ips_param_help = format!(
-        "Number of instructions per second for emulation, default, {}",
-        constants::EMULATION_IPS
+        "Number of instructions per second for emulation, default, {}, run as a whole number of instructions per {} Hz frame",
+        EMULATION_IPS,
+        constants::FRAMES_PER_SECOND
+    );
+    let frames_param_help = format!(
+        "Number of 60 Hz frames to run in headless mode, default {}",
+        HEADLESS_FRAMES
+    );
+    let stack_param_help = format!(
+        "Number of subroutine stack levels, default {}, at most {}",
+        constants::STACK_LEVELS,
+        constants::MAX_STACK_LEVELS
+    );
+    let quirk_param_help = format!(
+        "Override a single quirk of the preset as `<name>=<on|off>`, name one of: {}",
+        Quirks::NAMES.join(", ")
     );
     let matches = Command::new("CHIP-8")
         .version("0.1")
         .author("Marin-Georign Badita")
         .about("Simple Chip-8 emulator")
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("assemble")
+                .about("Assemble a source file into a ROM")
+                .arg(
+                    Arg::new("source-path")
+                        .required(true)
+                        .index(1)
+                        .help("Path to the assembly source"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .required(false)
+                        .short('o')
+                        .long("output")
+                        .takes_value(true)
+                        .help("ROM file to write, default the source path with a .ch8 extension"),
+                )
+                .arg(
+                    Arg::new("symbols")
+                        .required(false)
+                        .long("symbols")
+                        .takes_value(true)
+                        .help("File to write the address of every label to"),
+                ),
+        )
+        .subcommand(
+            Command::new("disasm")
+                .about("Print the instructions of a ROM")
+                .arg(
+                    Arg::new("rom-path")
+                        .required(true)
+                        .index(1)
+                        .help("Path to ROM file"),
+                )
+                .arg(
+                    Arg::new("recursive")
+                        .required(false)
+                        .short('r')
+                        .long("recursive")
+                        .takes_value(false)
+                        .help("Follow jumps and calls from the entry point to tell code from data"),
+                )
+                .arg(
+                    Arg::new("syntax")
+                        .required(false)
+                        .long("syntax")
+                        .takes_value(true)
+                        .possible_values(Syntax::NAMES)
+                        .default_value("cowgod")
+                        .help("Mnemonics of the output"),
+                ),
+        )
         .arg(
             Arg::new("rom-path")
                 .required(true)
@@ -42,6 +133,105 @@ fn main() {
                 .takes_value(true)
                 .help(ips_param_help.as_str()),
         )
+        .arg(
+            Arg::new("stack-depth")
+                .required(false)
+                .long("stack-depth")
+                .takes_value(true)
+                .help(stack_param_help.as_str()),
+        )
+        .arg(
+            Arg::new("quirks")
+                .required(false)
+                .long("quirks")
+                .takes_value(true)
+                .possible_values(Quirks::PRESETS)
+                .default_value("cowgod")
+                .help("Interpreter whose behaviours are emulated"),
+        )
+        .arg(
+            Arg::new("quirk")
+                .required(false)
+                .long("quirk")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help(quirk_param_help.as_str()),
+        )
+        .arg(
+            Arg::new("load-state")
+                .required(false)
+                .long("load-state")
+                .takes_value(true)
+                .help("Save state file to boot into"),
+        )
+        .arg(
+            Arg::new("seed")
+                .required(false)
+                .long("seed")
+                .takes_value(true)
+                .help("Seed of the CXNN random number generator, random by default"),
+        )
+        .arg(
+            Arg::new("vip-rng")
+                .required(false)
+                .long("vip-rng")
+                .takes_value(true)
+                .help(
+                    "Dump of the 512 byte COSMAC VIP interpreter, enables its CXNN random numbers",
+                ),
+        )
+        .arg(
+            Arg::new("record")
+                .required(false)
+                .long("record")
+                .takes_value(true)
+                .conflicts_with_all(&["play", "load-state", "headless", "debugger", "gdb"])
+                .help("Movie file to record the keys of every frame to"),
+        )
+        .arg(
+            Arg::new("play")
+                .required(false)
+                .long("play")
+                .takes_value(true)
+                .conflicts_with_all(&["load-state", "headless", "debugger", "gdb"])
+                .help("Movie file to play back"),
+        )
+        .arg(
+            Arg::new("trace")
+                .required(false)
+                .long("trace")
+                .takes_value(true)
+                .help("File to write a line for every executed instruction to"),
+        )
+        .arg(
+            Arg::new("trace-format")
+                .required(false)
+                .long("trace-format")
+                .takes_value(true)
+                .requires("trace")
+                .possible_values(TraceFormat::NAMES)
+                .default_value("text")
+                .help("Layout of the trace lines"),
+        )
+        .arg(
+            Arg::new("trace-range")
+                .required(false)
+                .long("trace-range")
+                .takes_value(true)
+                .requires("trace")
+                .help("Only trace instructions in the address range `<start>-<end>`, e.g. `0x200-0x2FF`"),
+        )
+        .arg(
+            Arg::new("trace-class")
+                .required(false)
+                .long("trace-class")
+                .takes_value(true)
+                .requires("trace")
+                .multiple_occurrences(true)
+                .use_value_delimiter(true)
+                .possible_values(OpcodeClass::NAMES)
+                .help("Only trace instructions of the given classes"),
+        )
         .arg(
             Arg::new("debug")
                 .required(false)
@@ -49,29 +239,386 @@ fn main() {
                 .takes_value(false)
                 .help("Whether the emulator is run in debug mode"),
         )
+        .arg(
+            Arg::new("headless")
+                .required(false)
+                .long("headless")
+                .takes_value(false)
+                .help("Run without video or audio and dump the final machine state"),
+        )
+        .arg(
+            Arg::new("debugger")
+                .required(false)
+                .long("debugger")
+                .takes_value(false)
+                .conflicts_with("headless")
+                .help("Run without video or audio, stepping through the ROM from a command prompt"),
+        )
+        .arg(
+            Arg::new("gdb")
+                .required(false)
+                .long("gdb")
+                .takes_value(true)
+                .conflicts_with_all(&["headless", "debugger"])
+                .help("Local TCP port to wait for a GDB remote protocol client on"),
+        )
+        .arg(
+            Arg::new("frontend")
+                .required(false)
+                .long("frontend")
+                .takes_value(true)
+                .possible_values(["sdl", "tui"])
+                .default_value("sdl")
+                .help("Draw in an SDL window, or in the terminal for machines without a display"),
+        )
+        .arg(
+            Arg::new("glyphs")
+                .required(false)
+                .long("glyphs")
+                .takes_value(true)
+                .possible_values(Glyphs::NAMES)
+                .default_value("half-block")
+                .help("Characters the terminal frontend draws the screen with"),
+        )
+        .arg(
+            Arg::new("symbols")
+                .required(false)
+                .long("symbols")
+                .takes_value(true)
+                .requires("debugger")
+                .help(
+                    "Symbol file written by `assemble --symbols` to name addresses in the debugger",
+                ),
+        )
+        .arg(
+            Arg::new("cycles")
+                .required(false)
+                .long("cycles")
+                .takes_value(true)
+                .requires("headless")
+                .conflicts_with("frames")
+                .help("Number of cycles to run in headless mode"),
+        )
+        .arg(
+            Arg::new("frames")
+                .required(false)
+                .long("frames")
+                .takes_value(true)
+                .requires("headless")
+                .help(frames_param_help.as_str()),
+        )
+        .arg(
+            Arg::new("keys")
+                .required(false)
+                .long("keys")
+                .takes_value(true)
+                .requires("headless")
+                .help("Key script for headless mode, one `<frame> <down|up> <key>` per line"),
+        )
+        .arg(
+            Arg::new("dump")
+                .required(false)
+                .long("dump")
+                .takes_value(true)
+                .requires("headless")
+                .help("File to write the final machine state to, default stdout"),
+        )
         .get_matches();
 
+    if let Some(assemble_matches) = matches.subcommand_matches("assemble") {
+        assemble(assemble_matches);
+        return;
+    }
+    if let Some(disasm_matches) = matches.subcommand_matches("disasm") {
+        disasm(disasm_matches);
+        return;
+    }
+
     let rom_path = matches.value_of("rom-path").unwrap();
     let scale = if matches.is_present("scale") {
         matches
             .value_of("scale")
             .unwrap_or_default()
             .parse::<u32>()
-            .unwrap_or(constants::DEF_SCALE)
+            .unwrap_or(DEF_SCALE)
     } else {
-        constants::DEF_SCALE
+        DEF_SCALE
     };
     let emulation_ips = if matches.is_present("emulation-ips") {
         matches
             .value_of("emulation-ips")
             .unwrap_or_default()
             .parse::<u128>()
-            .unwrap_or(constants::EMULATION_IPS)
+            .unwrap_or(EMULATION_IPS)
     } else {
-        constants::EMULATION_IPS
+        EMULATION_IPS
     };
+    let cycles_per_frame = ((emulation_ips + constants::FRAMES_PER_SECOND / 2)
+        / constants::FRAMES_PER_SECOND)
+        .max(1) as u32;
+    let stack_depth = matches
+        .value_of("stack-depth")
+        .map(|depth| {
+            depth
+                .parse::<usize>()
+                .ok()
+                .filter(|depth| (1..=constants::MAX_STACK_LEVELS).contains(depth))
+                .unwrap_or_else(|| {
+                    eprintln!(
+                        "Invalid stack depth {}, expected 1 to {}",
+                        depth,
+                        constants::MAX_STACK_LEVELS
+                    );
+                    process::exit(1);
+                })
+        })
+        .unwrap_or(constants::STACK_LEVELS);
+    let mut quirks = matches
+        .value_of("quirks")
+        .and_then(Quirks::from_preset)
+        .unwrap_or_default();
+    for quirk in matches.values_of("quirk").unwrap_or_default() {
+        if let Err(err) = quirks.apply_override(quirk) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
     let debug = matches.occurrences_of("debug") > 0;
+    let seed = matches.value_of("seed").map(|seed| {
+        seed.parse::<u64>().unwrap_or_else(|_| {
+            eprintln!("Invalid seed {}", seed);
+            process::exit(1);
+        })
+    });
+    let rng = match matches.value_of("vip-rng") {
+        Some(interpreter_path) => {
+            let vip_page = Rng::read_vip_page(interpreter_path).unwrap_or_else(|err| exit(err));
+            Some(Rng::vip(seed.unwrap_or_else(random), vip_page))
+        }
+        None => seed.map(Rng::new),
+    };
+
+    let tracer = matches.value_of("trace").map(|trace_path| {
+        let format = matches
+            .value_of("trace-format")
+            .and_then(TraceFormat::from_name)
+            .unwrap_or(TraceFormat::Text);
+        let addresses = matches.value_of("trace-range").map(|range| {
+            TraceFilter::parse_range(range).unwrap_or_else(|| {
+                eprintln!("Invalid trace range {}", range);
+                process::exit(1);
+            })
+        });
+        let classes = matches
+            .values_of("trace-class")
+            .unwrap_or_default()
+            .filter_map(OpcodeClass::from_name)
+            .collect();
+        let filter = TraceFilter { addresses, classes };
+        Tracer::create(trace_path, format, filter).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        })
+    });
+
+    if matches.is_present("headless") {
+        let count = |name: &str, value: &str| {
+            value.parse::<u64>().unwrap_or_else(|_| {
+                eprintln!("Invalid number of {} {}", name, value);
+                process::exit(1);
+            })
+        };
+        let run_length = match (matches.value_of("cycles"), matches.value_of("frames")) {
+            (Some(cycles), _) => RunLength::Cycles(count("cycles", cycles)),
+            (None, Some(frames)) => RunLength::Frames(count("frames", frames)),
+            (None, None) => RunLength::Frames(HEADLESS_FRAMES),
+        };
+        let keypad = match matches.value_of("keys") {
+            Some(script_path) => ScriptedKeypad::from_file(script_path),
+            None => ScriptedKeypad::new(""),
+        };
+        let keypad = keypad.unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        let mut headless = Headless::new(
+            rom_path,
+            cycles_per_frame,
+            stack_depth,
+            quirks,
+            keypad,
+            debug,
+        )
+        .unwrap_or_else(|err| exit(err));
+        if let Some(rng) = rng {
+            headless.set_rng(rng);
+        }
+        if let Some(tracer) = tracer {
+            headless.set_tracer(tracer);
+        }
+        if let Some(state_path) = matches.value_of("load-state") {
+            headless
+                .load_state_file(state_path)
+                .unwrap_or_else(|err| exit(err));
+        }
+        headless.run(run_length).unwrap_or_else(|err| exit(err));
+
+        let dump = headless.dump();
+        match matches.value_of("dump") {
+            Some(dump_path) => {
+                if let Err(err) = fs::write(dump_path, dump) {
+                    eprintln!("Could not write dump to {}: {}", dump_path, err);
+                    process::exit(1);
+                }
+            }
+            None => print!("{}", dump),
+        }
+        return;
+    }
+
+    if matches.is_present("debugger") {
+        let mut debugger = Debugger::new(rom_path, cycles_per_frame, stack_depth, quirks, debug)
+            .unwrap_or_else(|err| exit(err));
+        if let Some(rng) = rng {
+            debugger.set_rng(rng);
+        }
+        if let Some(tracer) = tracer {
+            debugger.set_tracer(tracer);
+        }
+        if let Some(state_path) = matches.value_of("load-state") {
+            debugger
+                .load_state_file(state_path)
+                .unwrap_or_else(|err| exit(err));
+        }
+        if let Some(symbols_path) = matches.value_of("symbols") {
+            if let Err(err) = debugger.load_symbols(symbols_path) {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+        debugger.run();
+        return;
+    }
+
+    if matches.value_of("frontend") == Some("tui") {
+        for sdl_only in ["gdb", "record", "play"] {
+            if matches.is_present(sdl_only) {
+                eprintln!("--{} is not supported by the terminal frontend", sdl_only);
+                process::exit(1);
+            }
+        }
+        let glyphs = matches
+            .value_of("glyphs")
+            .and_then(Glyphs::from_name)
+            .unwrap_or(Glyphs::HalfBlock);
+        let mut tui = Tui::new(rom_path, cycles_per_frame, stack_depth, quirks, glyphs)
+            .unwrap_or_else(|err| exit(err));
+        if let Some(rng) = rng {
+            tui.set_rng(rng);
+        }
+        if let Some(tracer) = tracer {
+            tui.set_tracer(tracer);
+        }
+        if let Some(state_path) = matches.value_of("load-state") {
+            tui.load_state_file(state_path)
+                .unwrap_or_else(|err| exit(err));
+        }
+        tui.run().unwrap_or_else(|err| exit(err));
+        return;
+    }
+
+    // GDB attaches before the window opens, which would stop responding while waiting
+    let gdb = matches.value_of("gdb").map(|port| {
+        let port = port.parse::<u16>().unwrap_or_else(|_| {
+            eprintln!("Invalid port {}", port);
+            process::exit(1);
+        });
+        GdbStub::listen(port).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        })
+    });
+    let mut emulator = Emulator::new(
+        "CHIP-8 Emulation",
+        rom_path,
+        scale,
+        cycles_per_frame,
+        stack_depth,
+        quirks,
+        debug,
+    )
+    .unwrap_or_else(|err| exit(err));
+    if let Some(rng) = rng {
+        emulator.set_rng(rng);
+    }
+    if let Some(tracer) = tracer {
+        emulator.set_tracer(tracer);
+    }
+    if let Some(state_path) = matches.value_of("load-state") {
+        emulator
+            .load_state_file(state_path)
+            .unwrap_or_else(|err| exit(err));
+    }
+    if let Some(gdb) = gdb {
+        emulator.attach_gdb(gdb);
+    }
+    if let Some(movie_path) = matches.value_of("record") {
+        emulator
+            .record_movie(movie_path)
+            .unwrap_or_else(|err| exit(err));
+    }
+    if let Some(movie_path) = matches.value_of("play") {
+        emulator
+            .play_movie(movie_path)
+            .unwrap_or_else(|err| exit(err));
+    }
+    emulator.emulate().unwrap_or_else(|err| exit(err));
+}
+
+// Writes the ROM assembled from a source file, and its symbols if asked to
+fn assemble(matches: &ArgMatches) {
+    let source_path = matches.value_of("source-path").unwrap();
+    let output_path = matches
+        .value_of("output")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(source_path).with_extension("ch8"));
+    let assembly = asm::assemble_file(source_path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let mut outputs = vec![(output_path, assembly.binary.clone())];
+    if let Some(symbols_path) = matches.value_of("symbols") {
+        outputs.push((
+            PathBuf::from(symbols_path),
+            assembly.symbol_file().into_bytes(),
+        ));
+    }
+    for (path, contents) in outputs {
+        if let Err(err) = fs::write(&path, contents) {
+            eprintln!("Could not write {}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+}
+
+// Prints the disassembly of a ROM
+fn disasm(matches: &ArgMatches) {
+    let rom_path = matches.value_of("rom-path").unwrap();
+    let rom =
+        fs::read(rom_path).unwrap_or_else(|_| exit(Chip8Error::RomNotFound(rom_path.to_string())));
+    let syntax = matches
+        .value_of("syntax")
+        .and_then(Syntax::from_name)
+        .unwrap_or(Syntax::Cowgod);
+    print!(
+        "{}",
+        disasm::disassemble(&rom, matches.is_present("recursive"), syntax)
+    );
+}
 
-    let mut emulator = Emulator::new("CHIP-8 Emulation", rom_path, scale, emulation_ips, debug);
-    emulator.emulate();
+// Reports the error and exits with its exit code
+fn exit(err: Chip8Error) -> ! {
+    eprintln!("Error: {}", err);
+    process::exit(err.exit_code());
 }
@@ -6,7 +6,7 @@ use sdl2::rect::Rect;
 use sdl2::Sdl;
 use sdl2::{render::Canvas, video::Window, EventPump};
 
-use crate::constants;
+use chip8_core::constants;
 
 // SDL Screen for emulator
 pub struct Screen {
@@ -16,8 +16,13 @@ pub struct Screen {
 }
 
 impl Screen {
-    const BLACK_COLOR: Color = Color::RGB(255, 255, 255);
-    const WHITE_COLOR: Color = Color::RGB(0, 0, 0);
+    // Colors indexed by the XO-CHIP plane bits of a pixel
+    const PALETTE: [Color; 4] = [
+        Color::RGB(0, 0, 0),
+        Color::RGB(255, 255, 255),
+        Color::RGB(170, 170, 170),
+        Color::RGB(85, 85, 85),
+    ];
 
     pub fn new(sdl: &Sdl, title: &str, scale: u32) -> Self {
         let video_subsystem = sdl.video().unwrap();
@@ -43,27 +48,30 @@ impl Screen {
 
     // Clears the display
     pub fn clear_screen(&mut self) {
-        self.canvas.set_draw_color(Self::BLACK_COLOR);
+        self.canvas.set_draw_color(Self::PALETTE[0]);
         self.canvas.clear();
         self.canvas.present();
     }
 
-    // Updates screen with buffer
-    pub fn update_screen(&mut self, buffer: &[u8; constants::SCREEN_SIZE]) {
-        let scale = self.scale as usize;
+    // Updates screen with a buffer of the given resolution, stretched over the window
+    pub fn update_screen(&mut self, buffer: &[u8], width: usize, height: usize) {
+        let window_width = constants::SCREEN_WIDTH * self.scale as usize;
+        let window_height = constants::SCREEN_HEIGHT * self.scale as usize;
 
-        for x in 0..constants::SCREEN_WIDTH {
-            for y in 0..constants::SCREEN_HEIGHT {
-                match buffer[y * constants::SCREEN_WIDTH + x] > 0 {
-                    true => self.canvas.set_draw_color(Self::BLACK_COLOR),
-                    false => self.canvas.set_draw_color(Self::WHITE_COLOR),
-                }
+        for x in 0..width {
+            for y in 0..height {
+                self.canvas
+                    .set_draw_color(Self::PALETTE[buffer[y * width + x] as usize & 0x03]);
+                let left = x * window_width / width;
+                let top = y * window_height / height;
+                let right = (x + 1) * window_width / width;
+                let bottom = (y + 1) * window_height / height;
                 self.canvas
                     .fill_rect(Rect::new(
-                        (x * scale) as i32,
-                        (y * scale) as i32,
-                        scale as u32,
-                        scale as u32,
+                        left as i32,
+                        top as i32,
+                        (right - left) as u32,
+                        (bottom - top) as u32,
                     ))
                     .unwrap();
             }
@@ -76,19 +84,35 @@ struct SquareWave {
     phase_inc: f32,
     phase: f32,
     volume: f32,
+    // XO-CHIP 1-bit audio pattern, played instead of the square wave when loaded
+    pattern: Option<[u8; constants::AUDIO_PATTERN_SIZE]>,
+    // Pattern bits played per output sample
+    pattern_inc: f32,
+    // Position in the pattern [bits]
+    pattern_phase: f32,
 }
 impl AudioCallback for SquareWave {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
+        const PATTERN_BITS: f32 = (constants::AUDIO_PATTERN_SIZE * 8) as f32;
+
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
+            let high = match self.pattern {
+                // Play the pattern bit by bit
+                Some(pattern) => {
+                    let bit = self.pattern_phase as usize;
+                    self.pattern_phase = (self.pattern_phase + self.pattern_inc) % PATTERN_BITS;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) > 0
+                }
+                // Generate a square wave
+                None => {
+                    let high = self.phase <= 0.5;
+                    self.phase = (self.phase + self.phase_inc) % 1.0;
+                    high
+                }
             };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+            *x = if high { self.volume } else { -self.volume };
         }
     }
 }
@@ -96,6 +120,11 @@ impl AudioCallback for SquareWave {
 // SDL Beep for emulator
 pub struct Beep {
     device: AudioDevice<SquareWave>,
+    // Output sample rate [Hz]
+    freq: f32,
+    // Pattern and playback rate currently set on the device
+    pattern: Option<[u8; constants::AUDIO_PATTERN_SIZE]>,
+    pattern_rate: f32,
 }
 
 impl Beep {
@@ -115,11 +144,38 @@ impl Beep {
                     phase_inc: 440.0 / spec.freq as f32,
                     phase: 0.0,
                     volume: 0.2,
+                    pattern: None,
+                    pattern_inc: 0.0,
+                    pattern_phase: 0.0,
                 }
             })
             .unwrap();
+        let freq = device.spec().freq as f32;
+
+        Beep {
+            device,
+            freq,
+            pattern: None,
+            pattern_rate: 0.0,
+        }
+    }
+
+    // Plays the given XO-CHIP audio pattern at the given rate [bits/s] instead of the square wave
+    pub fn set_pattern(
+        &mut self,
+        pattern: Option<&[u8; constants::AUDIO_PATTERN_SIZE]>,
+        pattern_rate: f32,
+    ) {
+        let pattern = pattern.copied();
+        if pattern == self.pattern && pattern_rate == self.pattern_rate {
+            return;
+        }
+        self.pattern = pattern;
+        self.pattern_rate = pattern_rate;
 
-        Beep { device }
+        let mut wave = self.device.lock();
+        wave.pattern = pattern;
+        wave.pattern_inc = pattern_rate / self.freq;
     }
 
     pub fn play(&self) {
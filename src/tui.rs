@@ -0,0 +1,423 @@
+use std::collections::VecDeque;
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chip8_core::chip8::Chip8;
+use chip8_core::constants;
+use chip8_core::error::Chip8Error;
+use chip8_core::keypad::{KeyEvent, Keypad};
+use chip8_core::quirks::Quirks;
+use chip8_core::rng::Rng;
+use chip8_core::state;
+use chip8_core::trace::Tracer;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+
+// Frames a key stays pressed without an auto-repeat, longer than the interval between
+// keyboard auto-repeats
+const KEY_HOLD_FRAMES: u32 = 8;
+
+// Characters the screen is drawn with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Glyphs {
+    // One character per 1x2 pixels, in the colors of the XO-CHIP planes
+    HalfBlock,
+    // One character per 2x4 pixels, lit for any plane
+    Braille,
+}
+
+impl Glyphs {
+    pub const NAMES: [&'static str; 2] = ["half-block", "braille"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "half-block" => Some(Glyphs::HalfBlock),
+            "braille" => Some(Glyphs::Braille),
+            _ => None,
+        }
+    }
+}
+
+// Converts typed characters into keys, with the layout of `keys::unmap`
+fn unmap(character: char) -> Option<u8> {
+    const LAYOUT: [char; constants::NUM_KEYS] = [
+        'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+    ];
+    LAYOUT
+        .iter()
+        .position(|&key| key == character.to_ascii_lowercase())
+        .map(|key| key as u8)
+}
+
+// Keypad fed with the key presses of a raw mode terminal
+// Most terminals only report presses and auto-repeats, so a key counts as released once it
+// has not been repeated for a few frames
+pub struct TerminalKeypad {
+    // Frames left until each key is released, 0 for released keys
+    held: [u32; constants::NUM_KEYS],
+    events: VecDeque<KeyEvent>,
+    // Terminal reports key releases itself
+    reports_releases: bool,
+}
+
+impl TerminalKeypad {
+    fn new(reports_releases: bool) -> Self {
+        TerminalKeypad {
+            held: [0; constants::NUM_KEYS],
+            events: VecDeque::new(),
+            reports_releases,
+        }
+    }
+
+    fn press(&mut self, key: u8) {
+        let held = &mut self.held[key as usize];
+        if *held == 0 {
+            self.events.push_back(KeyEvent::Down(key));
+        }
+        *held = if self.reports_releases {
+            u32::MAX
+        } else {
+            KEY_HOLD_FRAMES
+        };
+    }
+
+    fn release(&mut self, key: u8) {
+        if self.held[key as usize] > 0 {
+            self.held[key as usize] = 0;
+            self.events.push_back(KeyEvent::Up(key));
+        }
+    }
+
+    // Releases the keys that were not repeated in time
+    fn end_frame(&mut self) {
+        if self.reports_releases {
+            return;
+        }
+        for key in 0..constants::NUM_KEYS {
+            if self.held[key] == 1 {
+                self.release(key as u8);
+            } else if self.held[key] > 1 {
+                self.held[key] -= 1;
+            }
+        }
+    }
+}
+
+impl Keypad for TerminalKeypad {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.held.get(key as usize).is_some_and(|&held| held > 0)
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+}
+
+// Raw mode alternate screen, restored when dropped, even when unwinding from a panic
+struct Terminal {
+    stdout: Stdout,
+    reports_releases: bool,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut terminal = Terminal {
+            stdout: io::stdout(),
+            reports_releases: false,
+        };
+        execute!(
+            terminal.stdout,
+            EnterAlternateScreen,
+            cursor::Hide,
+            Clear(ClearType::All)
+        )?;
+        // Terminals with the kitty keyboard protocol report key releases
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            execute!(
+                terminal.stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+            terminal.reports_releases = true;
+        }
+        Ok(terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.reports_releases {
+            let _ = execute!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.stdout, ResetColor, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Terminal frontend, for machines without a display server
+pub struct Tui {
+    chip8: Chip8,
+    glyphs: Glyphs,
+    // Sound timer running on the previous frame, the terminal bell rings when it starts
+    beeping: bool,
+    // Instructions per second over the last full second
+    ips: u64,
+    // Screen width drawn last, the terminal is cleared when the resolution changes
+    width: usize,
+    // Whole terminal needs drawing again, e.g. after a resize
+    redraw: bool,
+}
+
+impl Tui {
+    // Colors indexed by the XO-CHIP plane bits of a pixel, as in `media::Screen`
+    const PALETTE: [Color; 4] = [Color::Black, Color::White, Color::Grey, Color::DarkGrey];
+
+    pub fn new(
+        rom_path: &str,
+        cycles_per_frame: u32,
+        stack_depth: usize,
+        quirks: Quirks,
+        glyphs: Glyphs,
+    ) -> Result<Self, Chip8Error> {
+        let chip8 = Chip8::new(rom_path, cycles_per_frame, stack_depth, quirks, false)?;
+        Ok(Tui {
+            chip8,
+            glyphs,
+            beeping: false,
+            ips: 0,
+            width: 0,
+            redraw: true,
+        })
+    }
+
+    // Boots into the snapshot stored in the given file
+    pub fn load_state_file(&mut self, state_path: &str) -> Result<(), Chip8Error> {
+        let state = state::read_state_file(state_path)?;
+        self.chip8.load_state(&state)
+    }
+
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.chip8.set_rng(rng);
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.chip8.set_tracer(tracer);
+    }
+
+    // Runs one frame every 1/60 s until Esc or Ctrl+C is pressed or the program exits
+    pub fn run(&mut self) -> Result<(), Chip8Error> {
+        // Errors leave through `?`, dropping the terminal restores it before they are reported
+        let mut terminal = Terminal::open().map_err(|err| {
+            Chip8Error::Frontend(format!("Could not set up the terminal: {}", err))
+        })?;
+        let mut keypad = TerminalKeypad::new(terminal.reports_releases);
+
+        let frame_duration = Duration::from_nanos(crate::FRAME_NANOS);
+        let mut next_frame = Instant::now();
+        let mut second_start = next_frame;
+        let mut instructions = 0;
+        while self.handle_input(&mut keypad) {
+            if !self.chip8.is_waiting_for_key() {
+                instructions += self.chip8.get_cycles_per_frame() as u64;
+            }
+            self.chip8.run_frame(&mut keypad)?;
+            keypad.end_frame();
+
+            if second_start.elapsed() >= Duration::from_secs(1) {
+                self.ips = instructions;
+                instructions = 0;
+                second_start = Instant::now();
+            }
+            self.present(&mut terminal.stdout).map_err(|err| {
+                Chip8Error::Frontend(format!("Could not draw to the terminal: {}", err))
+            })?;
+
+            // Program stopped itself with 00FD
+            if self.chip8.has_exited() {
+                break;
+            }
+
+            // Wait for the next frame, skipping the missed ones when running late
+            next_frame += frame_duration;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+        Ok(())
+    }
+
+    // Feeds the pending key presses to the keypad, false once the user quits
+    fn handle_input(&mut self, keypad: &mut TerminalKeypad) -> bool {
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            let key_event = match event::read() {
+                Ok(Event::Key(key_event)) => key_event,
+                // A resized terminal is cleared, draw everything again
+                Ok(Event::Resize(..)) => {
+                    self.redraw = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            match key_event.code {
+                KeyCode::Esc => return false,
+                KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return false
+                }
+                KeyCode::Char(character) => {
+                    if let Some(key) = unmap(character) {
+                        match key_event.kind {
+                            KeyEventKind::Press | KeyEventKind::Repeat => keypad.press(key),
+                            KeyEventKind::Release => keypad.release(key),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    // Draws the screen when it changed, and the status line below it
+    fn present(&mut self, stdout: &mut Stdout) -> io::Result<()> {
+        let width = self.chip8.get_screen_width();
+        if width != self.width {
+            self.width = width;
+            self.redraw = true;
+        }
+        if self.redraw {
+            queue!(stdout, Clear(ClearType::All))?;
+        }
+        if self.redraw || self.chip8.should_clear_screen() || self.chip8.should_update_screen() {
+            match self.glyphs {
+                Glyphs::HalfBlock => self.draw_half_blocks(stdout)?,
+                Glyphs::Braille => self.draw_braille(stdout)?,
+            }
+            self.redraw = false;
+        }
+
+        let beeping = self.chip8.should_beep();
+        if beeping && !self.beeping {
+            queue!(stdout, Print('\x07'))?;
+        }
+        self.beeping = beeping;
+
+        let status = format!(
+            "PC 0x{:03X}  IPS {}{}  |  keys 1234 QWER ASDF ZXCV  |  Esc quit",
+            self.chip8.get_program_counter(),
+            self.ips,
+            if self.chip8.is_waiting_for_key() {
+                "  waiting for key"
+            } else {
+                ""
+            }
+        );
+        let rows = match self.glyphs {
+            Glyphs::HalfBlock => self.chip8.get_screen_height() / 2,
+            Glyphs::Braille => self.chip8.get_screen_height() / 4,
+        };
+        queue!(
+            stdout,
+            cursor::MoveTo(0, rows as u16),
+            Print(status),
+            Clear(ClearType::UntilNewLine)
+        )?;
+        stdout.flush()
+    }
+
+    // Upper pixel in the foreground color, lower pixel in the background color
+    fn draw_half_blocks(&self, stdout: &mut Stdout) -> io::Result<()> {
+        let width = self.chip8.get_screen_width();
+        for (row, pixels) in self.chip8.get_screen().chunks(width * 2).enumerate() {
+            queue!(stdout, cursor::MoveTo(0, row as u16))?;
+            let (top, bottom) = pixels.split_at(width);
+            let mut colors = None;
+            for (&top, &bottom) in top.iter().zip(bottom) {
+                let cell_colors = (
+                    Self::PALETTE[top as usize & 0x03],
+                    Self::PALETTE[bottom as usize & 0x03],
+                );
+                if colors != Some(cell_colors) {
+                    queue!(
+                        stdout,
+                        SetForegroundColor(cell_colors.0),
+                        SetBackgroundColor(cell_colors.1)
+                    )?;
+                    colors = Some(cell_colors);
+                }
+                queue!(stdout, Print('▀'))?;
+            }
+            queue!(stdout, ResetColor)?;
+        }
+        Ok(())
+    }
+
+    // Dots of the 2x4 Braille cells, indexed by [y][x]
+    fn draw_braille(&self, stdout: &mut Stdout) -> io::Result<()> {
+        const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+        let width = self.chip8.get_screen_width();
+        let height = self.chip8.get_screen_height();
+        let screen = self.chip8.get_screen();
+        for row in 0..height / 4 {
+            let line: String = (0..width / 2)
+                .map(|column| {
+                    let mut dots = 0;
+                    for (y, row_dots) in DOTS.iter().enumerate() {
+                        for (x, dot) in row_dots.iter().enumerate() {
+                            if screen[(row * 4 + y) * width + column * 2 + x] != 0 {
+                                dots |= dot;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + dots).unwrap_or(' ')
+                })
+                .collect();
+            queue!(stdout, cursor::MoveTo(0, row as u16), Print(line))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_released_once_no_longer_repeated() {
+        let mut keypad = TerminalKeypad::new(false);
+        keypad.press(0xA);
+        for _ in 1..KEY_HOLD_FRAMES {
+            keypad.end_frame();
+            assert!(keypad.is_pressed(0xA));
+        }
+        // An auto-repeat holds the key again, without a second press
+        keypad.press(0xA);
+        for _ in 0..KEY_HOLD_FRAMES {
+            keypad.end_frame();
+        }
+        assert!(!keypad.is_pressed(0xA));
+        assert_eq!(keypad.next_event(), Some(KeyEvent::Down(0xA)));
+        assert_eq!(keypad.next_event(), Some(KeyEvent::Up(0xA)));
+        assert_eq!(keypad.next_event(), None);
+    }
+
+    #[test]
+    fn reported_releases_are_waited_for() {
+        let mut keypad = TerminalKeypad::new(true);
+        keypad.press(0x1);
+        for _ in 0..KEY_HOLD_FRAMES * 2 {
+            keypad.end_frame();
+        }
+        assert!(keypad.is_pressed(0x1));
+        keypad.release(0x1);
+        assert!(!keypad.is_pressed(0x1));
+    }
+}